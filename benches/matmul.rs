@@ -0,0 +1,56 @@
+// Compares the blocked, multithreaded matrix products against the naive kernel
+// on the shapes a 784-100-100-10 MNIST network sees with a batch of 100.
+//
+// Run with `cargo bench --bench matmul`.
+
+#![allow(clippy::needless_return)]
+
+use std::time::{Duration, Instant};
+
+use neuralnetwork::Matrix;
+
+const TOLERANCE: f32 = 1e-3;
+
+fn time<F: FnMut() -> Matrix>(iterations: u32, mut f: F) -> (Duration, Matrix) {
+    let mut result = f();
+    let start = Instant::now();
+    for _ in 0..iterations {
+        result = f();
+    }
+    return (start.elapsed() / iterations, result);
+}
+
+fn max_difference(a: &Matrix, b: &Matrix) -> f32 {
+    assert_eq!(a.shape(), b.shape());
+    a.values().iter().zip(b.values()).map(|(x, y)| (x - y).abs()).fold(0.0, f32::max)
+}
+
+fn compare(name: &str, naive: (Duration, Matrix), fast: (Duration, Matrix)) {
+    let difference = max_difference(&naive.1, &fast.1);
+    assert!(difference < TOLERANCE, "{}: results differ by {}", name, difference);
+    println!("{:<40} naive {:>10.2?}  blocked {:>10.2?}  speedup {:>6.2}x  max diff {:e}",
+             name, naive.0, fast.0, naive.0.as_secs_f64() / fast.0.as_secs_f64(), difference);
+}
+
+fn main() {
+    let iterations = 10;
+    for &batch in &[100, 1000] {
+        let input = Matrix::new_random(batch, 784).unwrap();
+        let weights = Matrix::new_random(784, 100).unwrap();
+        let delta = Matrix::new_random(batch, 100).unwrap();
+
+        compare(&format!("forward {}x784 * 784x100", batch),
+                time(iterations, || Matrix::matrix_multiplication_naive(&input, &weights).unwrap()),
+                time(iterations, || Matrix::matrix_multiplication(&input, &weights).unwrap()));
+
+        let input_t = input.transpose();
+        compare(&format!("weights grad (784x{})ᵀ * {}x100", batch, batch),
+                time(iterations, || Matrix::matrix_multiplication_naive(&input_t, &delta).unwrap()),
+                time(iterations, || Matrix::matrix_multiplication_transposed_left(&input, &delta).unwrap()));
+
+        let weights_t = weights.transpose();
+        compare(&format!("input grad {}x100 * (784x100)ᵀ", batch),
+                time(iterations, || Matrix::matrix_multiplication_naive(&delta, &weights_t).unwrap()),
+                time(iterations, || Matrix::matrix_multiplication_transposed_right(&delta, &weights).unwrap()));
+    }
+}
@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::NnError;
+use crate::float::Float;
+use crate::matrix::Matrix;
+
+/// The activation function of a layer, bundled with its derivative so the two
+/// can never disagree.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Activation {
+    #[default]
+    Sigmoid,
+    Tanh,
+    Relu,
+    LeakyRelu { alpha: f64 },
+    Elu { alpha: f64 },
+    /// The tanh approximation of the Gaussian error linear unit.
+    Gelu,
+    Softplus,
+    Linear,
+    /// Normalizes every row into a probability distribution. Meant for the
+    /// output layer together with the categorical cross-entropy.
+    Softmax,
+}
+
+/// `sqrt(2 / pi)`, used by the GELU approximation.
+const GELU_SCALE: f64 = 0.797_884_560_802_865_4;
+const GELU_CUBIC: f64 = 0.044_715;
+
+fn sigmoid<T: Float>(x: T) -> T {
+    if x >= T::zero() {
+        T::one() / (T::one() + (-x).exp())
+    } else {
+        let e = x.exp();
+        e / (e + T::one())
+    }
+}
+
+impl Activation {
+    /// Applies an element-wise function to a single pre-activation value.
+    fn function<T: Float>(&self, x: T) -> T {
+        match *self {
+            Activation::Sigmoid => sigmoid(x),
+            Activation::Tanh => x.tanh(),
+            Activation::Relu => x.max(T::zero()),
+            Activation::LeakyRelu { alpha } => if x > T::zero() { x } else { T::from_f64(alpha) * x },
+            Activation::Elu { alpha } => if x > T::zero() { x } else { T::from_f64(alpha) * (x.exp() - T::one()) },
+            Activation::Gelu => {
+                let inner = T::from_f64(GELU_SCALE) * (x + T::from_f64(GELU_CUBIC) * x.powi(3));
+                T::from_f64(0.5) * x * (T::one() + inner.tanh())
+            }
+            // max(x, 0) + ln(1 + e^-|x|) does not overflow for large inputs.
+            Activation::Softplus => x.max(T::zero()) + (T::one() + (-x.abs()).exp()).ln(),
+            Activation::Linear => x,
+            Activation::Softmax => unreachable!("softmax is applied row-wise"),
+        }
+    }
+    /// The derivative of an element-wise function with respect to the
+    /// pre-activation `x`, given the output `y = f(x)`.
+    fn derivative<T: Float>(&self, x: T, y: T) -> T {
+        match *self {
+            Activation::Sigmoid => y * (T::one() - y),
+            Activation::Tanh => T::one() - y * y,
+            Activation::Relu => if x > T::zero() { T::one() } else { T::zero() },
+            Activation::LeakyRelu { alpha } => if x > T::zero() { T::one() } else { T::from_f64(alpha) },
+            Activation::Elu { alpha } => if x > T::zero() { T::one() } else { y + T::from_f64(alpha) },
+            Activation::Gelu => {
+                let scale = T::from_f64(GELU_SCALE);
+                let cubic = T::from_f64(GELU_CUBIC);
+                let t = (scale * (x + cubic * x.powi(3))).tanh();
+                let half = T::from_f64(0.5);
+                half * (T::one() + t) + half * x * (T::one() - t * t) * scale * (T::one() + T::from_f64(3.0) * cubic * x * x)
+            }
+            Activation::Softplus => sigmoid(x),
+            Activation::Linear => T::one(),
+            Activation::Softmax => unreachable!("softmax is differentiated row-wise"),
+        }
+    }
+
+    /// Applies the function to every pre-activation of a batch.
+    pub fn forward<T: Float>(&self, pre_activation: &Matrix<T>) -> Matrix<T> {
+        if let Activation::Softmax = self {
+            return softmax(pre_activation);
+        }
+        return pre_activation.apply_function(&|x| self.function(x));
+    }
+    /// Turns the gradient with respect to the outputs of a layer into the
+    /// gradient with respect to its pre-activations.
+    pub fn backward<T: Float>(&self, pre_activation: &Matrix<T>, output: &Matrix<T>, gradient: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        Matrix::check_same_shape("differentiate", pre_activation, output)?;
+        Matrix::check_same_shape("differentiate", output, gradient)?;
+        if let Activation::Softmax = self {
+            return Ok(softmax_backward(output, gradient));
+        }
+        let mut result = gradient.clone();
+        let derivatives = pre_activation.values().iter().zip(output.values()).map(|(&x, &y)| self.derivative(x, y));
+        for (g, d) in result.values_mut().iter_mut().zip(derivatives) {
+            *g *= d;
+        }
+        return Ok(result);
+    }
+}
+
+/// Row-wise softmax. The maximum of each row is subtracted first so that `exp`
+/// cannot overflow.
+fn softmax<T: Float>(pre_activation: &Matrix<T>) -> Matrix<T> {
+    let mut result = pre_activation.clone();
+    for i in 0..result.rows() {
+        let row = result.row_mut(i);
+        let max = row.iter().copied().fold(row[0], T::max);
+        let mut sum = T::zero();
+        for x in row.iter_mut() {
+            *x = (*x - max).exp();
+            sum += *x;
+        }
+        for x in row.iter_mut() {
+            *x /= sum;
+        }
+    }
+    return result;
+}
+
+/// Multiplies the gradient by the softmax Jacobian of each row:
+/// `dz_i = y_i * (g_i - sum_j g_j * y_j)`.
+fn softmax_backward<T: Float>(output: &Matrix<T>, gradient: &Matrix<T>) -> Matrix<T> {
+    let mut result = gradient.clone();
+    for i in 0..result.rows() {
+        let y = output.row(i);
+        let row = result.row_mut(i);
+        let dot: T = row.iter().zip(y).map(|(&g, &y)| g * y).sum();
+        for (g, &y) in row.iter_mut().zip(y) {
+            *g = y * (*g - dot);
+        }
+    }
+    return result;
+}
@@ -0,0 +1,339 @@
+//! A compact binary model format.
+//!
+//! All numbers are little-endian. A file starts with a 12 byte header: the
+//! magic bytes `NNMB`, the format version as a `u16`, the precision as a `u8`
+//! (0 for `f32`, 1 for `f64`), a reserved zero byte and the number of layers
+//! as a `u32`. A 17 byte record per layer follows with its input and output
+//! counts as `u32`s, its activation as a `u8` and the activation parameter as
+//! an `f64`. Then come the raw weights of every layer, row by row, each
+//! followed by its biases. The file ends with the CRC-32 of everything before
+//! it. Only the network itself is stored; the training configuration and
+//! metadata are kept by the JSON format alone.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::activation::Activation;
+use crate::error::NnError;
+use crate::float::{Float, Precision};
+use crate::layer::Layer;
+use crate::matrix::Matrix;
+use crate::network::Network;
+
+pub const MAGIC: [u8; 4] = *b"NNMB";
+pub const BINARY_VERSION: u16 = 1;
+const HEADER_SIZE: usize = 12;
+const LAYER_RECORD_SIZE: usize = 17;
+const CHECKSUM_SIZE: usize = 4;
+
+/// The CRC-32 (IEEE) checksum of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    return !crc;
+}
+
+fn encode_activation(activation: Activation) -> (u8, f64) {
+    match activation {
+        Activation::Sigmoid => (0, 0.0),
+        Activation::Tanh => (1, 0.0),
+        Activation::Relu => (2, 0.0),
+        Activation::LeakyRelu { alpha } => (3, alpha),
+        Activation::Elu { alpha } => (4, alpha),
+        Activation::Gelu => (5, 0.0),
+        Activation::Softplus => (6, 0.0),
+        Activation::Linear => (7, 0.0),
+        Activation::Softmax => (8, 0.0),
+    }
+}
+
+fn decode_activation(tag: u8, alpha: f64) -> Result<Activation, NnError> {
+    return Ok(match tag {
+        0 => Activation::Sigmoid,
+        1 => Activation::Tanh,
+        2 => Activation::Relu,
+        3 => Activation::LeakyRelu { alpha },
+        4 => Activation::Elu { alpha },
+        5 => Activation::Gelu,
+        6 => Activation::Softplus,
+        7 => Activation::Linear,
+        8 => Activation::Softmax,
+        _ => return Err(NnError::InvalidModel(format!("unknown activation {}", tag))),
+    });
+}
+
+fn precision_byte(precision: Precision) -> u8 {
+    match precision {
+        Precision::F32 => 0,
+        Precision::F64 => 1,
+    }
+}
+
+fn write_value<T: Float>(bytes: &mut Vec<u8>, value: T) {
+    match T::PRECISION {
+        Precision::F32 => bytes.extend_from_slice(&(value.to_f64() as f32).to_le_bytes()),
+        Precision::F64 => bytes.extend_from_slice(&value.to_f64().to_le_bytes()),
+    }
+}
+
+/// Reads consecutive little-endian values out of a byte slice.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], NnError> {
+        if self.bytes.len() - self.position < count {
+            return Err(NnError::InvalidModel("the file ends early".to_string()));
+        }
+        let slice = &self.bytes[self.position..self.position + count];
+        self.position += count;
+        return Ok(slice);
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], NnError> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.take(N)?);
+        return Ok(array);
+    }
+    fn u8(&mut self) -> Result<u8, NnError> {
+        return Ok(self.take(1)?[0]);
+    }
+    fn u16(&mut self) -> Result<u16, NnError> {
+        return Ok(u16::from_le_bytes(self.array()?));
+    }
+    fn u32(&mut self) -> Result<u32, NnError> {
+        return Ok(u32::from_le_bytes(self.array()?));
+    }
+    fn f64(&mut self) -> Result<f64, NnError> {
+        return Ok(f64::from_le_bytes(self.array()?));
+    }
+    fn value<T: Float>(&mut self) -> Result<T, NnError> {
+        return Ok(match T::PRECISION {
+            Precision::F32 => T::from_f32(f32::from_le_bytes(self.array()?)),
+            Precision::F64 => T::from_f64(self.f64()?),
+        });
+    }
+}
+
+/// Checks the magic bytes and the version at the start of a file and returns
+/// the precision of its values.
+fn read_header(reader: &mut Reader) -> Result<Precision, NnError> {
+    let magic = reader.array::<4>()?;
+    if magic != MAGIC {
+        return Err(NnError::BadMagic { expected: u32::from_le_bytes(MAGIC), found: u32::from_le_bytes(magic) });
+    }
+    let version = reader.u16()?;
+    if version > BINARY_VERSION {
+        return Err(NnError::UnsupportedVersion { found: version as u32, supported: BINARY_VERSION as u32 });
+    }
+    return match reader.u8()? {
+        0 => Ok(Precision::F32),
+        1 => Ok(Precision::F64),
+        byte => Err(NnError::InvalidModel(format!("unknown precision {}", byte))),
+    };
+}
+
+impl<T: Float> Network<T> {
+    /// Encodes the network in the binary model format.
+    pub fn to_binary(&self) -> Vec<u8> {
+        let parameters: usize = self.layers.iter().map(|layer| layer.weights.values().len() + layer.biases.values().len()).sum();
+        let value_size = match T::PRECISION {
+            Precision::F32 => 4,
+            Precision::F64 => 8,
+        };
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + LAYER_RECORD_SIZE * self.layers.len() + value_size * parameters + CHECKSUM_SIZE);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&BINARY_VERSION.to_le_bytes());
+        bytes.push(precision_byte(T::PRECISION));
+        bytes.push(0);
+        bytes.extend_from_slice(&(self.layers.len() as u32).to_le_bytes());
+        for layer in self.layers.iter() {
+            let (tag, alpha) = encode_activation(layer.activation);
+            bytes.extend_from_slice(&(layer.input_count() as u32).to_le_bytes());
+            bytes.extend_from_slice(&(layer.output_count() as u32).to_le_bytes());
+            bytes.push(tag);
+            bytes.extend_from_slice(&alpha.to_le_bytes());
+        }
+        for layer in self.layers.iter() {
+            for &value in layer.weights.values().iter().chain(layer.biases.values()) {
+                write_value(&mut bytes, value);
+            }
+        }
+        let checksum = crc32(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        return bytes;
+    }
+    /// Decodes a network from the binary model format, checking the checksum
+    /// and that the layers chain together.
+    pub fn from_binary(bytes: &[u8]) -> Result<Network<T>, NnError> {
+        if bytes.len() < HEADER_SIZE + CHECKSUM_SIZE {
+            return Err(NnError::InvalidModel("the file ends early".to_string()));
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_SIZE);
+        let mut reader = Reader { bytes: body, position: 0 };
+        let precision = read_header(&mut reader)?;
+        let found = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+        if crc32(body) != found {
+            return Err(NnError::InvalidModel("the checksum does not match, the file is corrupted".to_string()));
+        }
+        if precision != T::PRECISION {
+            return Err(NnError::PrecisionMismatch { expected: T::PRECISION, found: precision });
+        }
+        reader.u8()?;
+        let layer_count = reader.u32()? as usize;
+        let mut shapes = Vec::new();
+        for _ in 0..layer_count {
+            let inputs = reader.u32()? as usize;
+            let outputs = reader.u32()? as usize;
+            let activation = decode_activation(reader.u8()?, reader.f64()?)?;
+            shapes.push((inputs, outputs, activation));
+        }
+        let mut layers = Vec::with_capacity(layer_count);
+        for (inputs, outputs, activation) in shapes {
+            let mut read_matrix = |rows: usize, cols: usize| -> Result<Matrix<T>, NnError> {
+                let count = rows.checked_mul(cols).ok_or_else(|| NnError::InvalidModel("a layer is too large".to_string()))?;
+                let values = (0..count).map(|_| reader.value()).collect::<Result<Vec<T>, NnError>>()?;
+                return Matrix::from_flat(rows, cols, values);
+            };
+            let weights = read_matrix(inputs, outputs)?;
+            let biases = read_matrix(1, outputs)?;
+            layers.push(Layer { weights, biases, activation });
+        }
+        if reader.position != body.len() {
+            return Err(NnError::InvalidModel("there are bytes left after the last layer".to_string()));
+        }
+        return Network::from_layers(layers);
+    }
+    pub fn save_binary<P: AsRef<Path>>(&self, path: P) -> Result<(), NnError> {
+        // Renamed into place, like the JSON format, so a failed save cannot
+        // leave a truncated model behind.
+        let path = path.as_ref();
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, self.to_binary())?;
+        fs::rename(&temporary, path)?;
+        return Ok(());
+    }
+    pub fn load_binary<P: AsRef<Path>>(path: P) -> Result<Network<T>, NnError> {
+        return Network::from_binary(&fs::read(path)?);
+    }
+}
+
+/// Whether a file starts with the magic bytes of the binary model format.
+pub fn is_binary<P: AsRef<Path>>(path: P) -> Result<bool, NnError> {
+    let mut start = [0u8; 4];
+    let mut file = File::open(path)?;
+    let mut read = 0;
+    while read < start.len() {
+        match file.read(&mut start[read..])? {
+            0 => return Ok(false),
+            n => read += n,
+        }
+    }
+    return Ok(start == MAGIC);
+}
+
+/// The precision of a parsed JSON model or bare network.
+fn json_precision(value: &Value) -> Result<Precision, NnError> {
+    let network = value.get("network").unwrap_or(value);
+    return match network.get("precision") {
+        Some(precision) => Ok(Precision::deserialize(precision)?),
+        None => Ok(Precision::F32),
+    };
+}
+
+/// Converts a JSON model file into the binary format, keeping its precision.
+/// The file is parsed once and decoded in the precision it records.
+pub fn json_to_binary<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<(), NnError> {
+    let value: Value = serde_json::from_slice(&fs::read(from)?)?;
+    return match json_precision(&value)? {
+        Precision::F32 => Network::<f32>::from_json(value)?.0.save_binary(to),
+        Precision::F64 => Network::<f64>::from_json(value)?.0.save_binary(to),
+    };
+}
+
+/// Converts a binary model file into the JSON format, keeping its precision.
+pub fn binary_to_json<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<(), NnError> {
+    let bytes = fs::read(from)?;
+    return match read_header(&mut Reader { bytes: &bytes, position: 0 })? {
+        Precision::F32 => Network::<f32>::from_binary(&bytes)?.save(to),
+        Precision::F64 => Network::<f64>::from_binary(&bytes)?.save(to),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network<T: Float>() -> Network<T> {
+        return Network::from_layers(vec![
+            Layer::new(3, 4, Activation::LeakyRelu { alpha: 0.1 }).unwrap(),
+            Layer::new(4, 2, Activation::Softmax).unwrap(),
+        ]).unwrap();
+    }
+
+    fn assert_same<T: Float>(left: &Network<T>, right: &Network<T>) {
+        assert_eq!(left.layers.len(), right.layers.len());
+        for (a, b) in left.layers.iter().zip(&right.layers) {
+            assert_eq!(a.activation, b.activation);
+            assert_eq!(a.weights.shape(), b.weights.shape());
+            assert_eq!(a.weights.values(), b.weights.values());
+            assert_eq!(a.biases.values(), b.biases.values());
+        }
+    }
+
+    #[test]
+    fn round_trips_exactly() {
+        let single = network::<f32>();
+        assert_same(&single, &Network::from_binary(&single.to_binary()).unwrap());
+        let double = network::<f64>();
+        assert_same(&double, &Network::from_binary(&double.to_binary()).unwrap());
+        assert!(matches!(Network::<f32>::from_binary(&double.to_binary()), Err(NnError::PrecisionMismatch { .. })));
+    }
+
+    #[test]
+    fn rejects_corrupted_files() {
+        let bytes = network::<f32>().to_binary();
+        let mut corrupted = bytes.clone();
+        corrupted[HEADER_SIZE + 5] ^= 0x01;
+        assert!(matches!(Network::<f32>::from_binary(&corrupted), Err(NnError::InvalidModel(_))));
+        let mut corrupted = bytes.clone();
+        *corrupted.last_mut().unwrap() ^= 0x80;
+        assert!(matches!(Network::<f32>::from_binary(&corrupted), Err(NnError::InvalidModel(_))));
+        let mut corrupted = bytes.clone();
+        corrupted[0] = b'X';
+        assert!(matches!(Network::<f32>::from_binary(&corrupted), Err(NnError::BadMagic { .. })));
+        assert!(Network::<f32>::from_binary(&bytes[..bytes.len() - 9]).is_err());
+    }
+
+    #[test]
+    fn converts_between_json_and_binary() {
+        let directory = std::env::temp_dir().join(format!("binary-test-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let original = network::<f64>();
+        original.save(directory.join("model.json")).unwrap();
+        json_to_binary(directory.join("model.json"), directory.join("model.nnmb")).unwrap();
+        assert!(is_binary(directory.join("model.nnmb")).unwrap());
+        binary_to_json(directory.join("model.nnmb"), directory.join("back.json")).unwrap();
+        assert_same(&original, &Network::load(directory.join("back.json")).unwrap());
+        assert!(matches!(binary_to_json(directory.join("model.json"), directory.join("other.json")), Err(NnError::BadMagic { .. })));
+
+        // Saving over a model replaces it without leaving the temporary file behind.
+        let single = network::<f32>();
+        single.save_binary(directory.join("model.nnmb")).unwrap();
+        assert_same(&single, &Network::load(directory.join("model.nnmb")).unwrap());
+        let mut files: Vec<_> = fs::read_dir(&directory).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        files.sort();
+        assert_eq!(files, ["back.json", "model.json", "model.nnmb"]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,246 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::NnError;
+use crate::float::Float;
+use crate::network::Network;
+use crate::schedule::LrSchedule;
+use crate::trainer::{EpochMetrics, History, Metric};
+
+/// The parts of a training run that callbacks can read and steer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrainingState {
+    pub epoch: usize,
+    /// The number of optimizer steps taken so far.
+    pub step: u64,
+    /// The learning rate of the next optimizer step.
+    pub learning_rate: f64,
+    /// Ends training after the current epoch.
+    pub stop: bool,
+}
+
+/// The loss and accuracy of a single mini-batch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchMetrics {
+    pub epoch: usize,
+    pub batch: usize,
+    pub size: usize,
+    pub loss: f64,
+    pub accuracy: f64,
+}
+
+/// Hooks into the training loop of a `Trainer`.
+///
+/// Every hook does nothing by default, and callbacks run in the order they
+/// were added to the trainer.
+pub trait Callback<T: Float> {
+    fn on_epoch_start(&mut self, _state: &mut TrainingState, _network: &Network<T>) -> Result<(), NnError> {
+        return Ok(());
+    }
+    fn on_batch_start(&mut self, _state: &mut TrainingState, _network: &Network<T>) -> Result<(), NnError> {
+        return Ok(());
+    }
+    fn on_batch_end(&mut self, _metrics: &BatchMetrics, _state: &mut TrainingState, _network: &Network<T>) -> Result<(), NnError> {
+        return Ok(());
+    }
+    fn on_epoch_end(&mut self, _metrics: &EpochMetrics, _state: &mut TrainingState, _network: &Network<T>) -> Result<(), NnError> {
+        return Ok(());
+    }
+    fn on_train_end(&mut self, _history: &mut History, _network: &mut Network<T>) -> Result<(), NnError> {
+        return Ok(());
+    }
+    /// The state that a training checkpoint has to keep for the callback to
+    /// carry on after a resume, for callbacks that have one.
+    fn state(&self) -> Result<Option<Value>, NnError> {
+        return Ok(None);
+    }
+    /// Puts back a state returned by `state`.
+    fn restore_state(&mut self, _state: Value) -> Result<(), NnError> {
+        return Ok(());
+    }
+}
+
+/// Whether the learning rate schedule advances once per batch or once per epoch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleUnit {
+    Step,
+    Epoch,
+}
+
+/// Sets the learning rate from a schedule and reports the validation loss, or
+/// the training loss without a validation set, to it after every epoch.
+#[derive(Clone, Debug)]
+pub struct LrScheduler {
+    pub schedule: LrSchedule,
+    pub unit: ScheduleUnit,
+}
+
+impl LrScheduler {
+    pub fn new(schedule: LrSchedule, unit: ScheduleUnit) -> LrScheduler {
+        LrScheduler { schedule, unit }
+    }
+}
+
+impl<T: Float> Callback<T> for LrScheduler {
+    fn on_epoch_start(&mut self, state: &mut TrainingState, _network: &Network<T>) -> Result<(), NnError> {
+        if self.unit == ScheduleUnit::Epoch {
+            state.learning_rate = self.schedule.learning_rate(state.epoch as u64);
+        }
+        return Ok(());
+    }
+    fn on_batch_start(&mut self, state: &mut TrainingState, _network: &Network<T>) -> Result<(), NnError> {
+        if self.unit == ScheduleUnit::Step {
+            state.learning_rate = self.schedule.learning_rate(state.step);
+        }
+        return Ok(());
+    }
+    fn on_epoch_end(&mut self, metrics: &EpochMetrics, _state: &mut TrainingState, _network: &Network<T>) -> Result<(), NnError> {
+        self.schedule.observe(Metric::ValidationLoss.value(metrics));
+        return Ok(());
+    }
+    fn state(&self) -> Result<Option<Value>, NnError> {
+        return Ok(Some(serde_json::to_value(&self.schedule)?));
+    }
+    fn restore_state(&mut self, state: Value) -> Result<(), NnError> {
+        self.schedule = serde_json::from_value(state)?;
+        return Ok(());
+    }
+}
+
+/// Stops training once the monitored metric has not improved by more than
+/// `min_delta` for `patience` epochs in a row, and optionally puts the weights
+/// of the best epoch back into the network.
+#[derive(Clone, Debug)]
+pub struct EarlyStopping<T: Float = f32> {
+    pub monitor: Metric,
+    pub patience: usize,
+    pub min_delta: f64,
+    pub restore_best: bool,
+    best: Option<(usize, f64)>,
+    wait: usize,
+    snapshot: Option<Network<T>>,
+}
+
+/// The part of `EarlyStopping` that changes during training.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+struct EarlyStoppingState<T: Float> {
+    best: Option<(usize, f64)>,
+    wait: usize,
+    snapshot: Option<Network<T>>,
+}
+
+impl<T: Float> EarlyStopping<T> {
+    pub fn new(monitor: Metric, patience: usize, min_delta: f64) -> EarlyStopping<T> {
+        EarlyStopping { monitor, patience, min_delta, restore_best: true, best: None, wait: 0, snapshot: None }
+    }
+    /// The epoch with the best value of the monitored metric so far, and that value.
+    pub fn best(&self) -> Option<(usize, f64)> {
+        self.best
+    }
+}
+
+impl<T: Float> Callback<T> for EarlyStopping<T> {
+    fn on_epoch_start(&mut self, state: &mut TrainingState, _network: &Network<T>) -> Result<(), NnError> {
+        if state.epoch == 0 {
+            self.best = None;
+            self.wait = 0;
+            self.snapshot = None;
+        }
+        return Ok(());
+    }
+    fn on_epoch_end(&mut self, metrics: &EpochMetrics, state: &mut TrainingState, network: &Network<T>) -> Result<(), NnError> {
+        let value = self.monitor.value(metrics);
+        if self.best.is_none_or(|(_, best)| self.monitor.is_improvement(value, best, self.min_delta)) {
+            self.best = Some((metrics.epoch, value));
+            self.wait = 0;
+            if self.restore_best {
+                self.snapshot = Some(network.clone());
+            }
+        } else {
+            self.wait += 1;
+            if self.wait >= self.patience {
+                state.stop = true;
+            }
+        }
+        return Ok(());
+    }
+    fn on_train_end(&mut self, history: &mut History, network: &mut Network<T>) -> Result<(), NnError> {
+        if let Some(snapshot) = self.snapshot.take() {
+            *network = snapshot;
+            history.best_epoch = self.best.map(|(epoch, _)| epoch);
+        }
+        return Ok(());
+    }
+    fn state(&self) -> Result<Option<Value>, NnError> {
+        let state = EarlyStoppingState { best: self.best, wait: self.wait, snapshot: self.snapshot.clone() };
+        return Ok(Some(serde_json::to_value(&state)?));
+    }
+    fn restore_state(&mut self, state: Value) -> Result<(), NnError> {
+        let state: EarlyStoppingState<T> = serde_json::from_value(state)?;
+        self.best = state.best;
+        self.wait = state.wait;
+        self.snapshot = state.snapshot;
+        return Ok(());
+    }
+}
+
+/// Prints the metrics of every epoch, and of every `batch_interval`-th batch if set.
+#[derive(Clone, Debug, Default)]
+pub struct ProgressLogger {
+    pub batch_interval: Option<usize>,
+}
+
+impl ProgressLogger {
+    pub fn new() -> ProgressLogger {
+        ProgressLogger { batch_interval: None }
+    }
+    pub fn every_batches(batch_interval: usize) -> ProgressLogger {
+        ProgressLogger { batch_interval: Some(batch_interval) }
+    }
+}
+
+impl<T: Float> Callback<T> for ProgressLogger {
+    fn on_batch_end(&mut self, metrics: &BatchMetrics, state: &mut TrainingState, _network: &Network<T>) -> Result<(), NnError> {
+        if let Some(interval) = self.batch_interval {
+            if interval > 0 && metrics.batch.is_multiple_of(interval) {
+                println!("Epoch {} batch {}: learning rate {:.6}, loss {:.4}, accuracy {:.4}",
+                         metrics.epoch, metrics.batch, state.learning_rate, metrics.loss, metrics.accuracy);
+            }
+        }
+        return Ok(());
+    }
+    fn on_epoch_end(&mut self, metrics: &EpochMetrics, _state: &mut TrainingState, _network: &Network<T>) -> Result<(), NnError> {
+        print!("Epoch {}: learning rate {:.6}, train loss {:.4}, train accuracy {:.4}",
+               metrics.epoch, metrics.learning_rate, metrics.train_loss, metrics.train_accuracy);
+        if let (Some(loss), Some(accuracy)) = (metrics.validation_loss, metrics.validation_accuracy) {
+            print!(", validation loss {:.4}, validation accuracy {:.4}", loss, accuracy);
+        }
+        println!();
+        return Ok(());
+    }
+}
+
+/// Saves the network every `interval` epochs. `{epoch}` in the path
+/// is replaced by the number of the epoch.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    pub path: String,
+    pub interval: usize,
+}
+
+impl Checkpoint {
+    pub fn new(path: &str, interval: usize) -> Checkpoint {
+        Checkpoint { path: path.to_string(), interval }
+    }
+}
+
+impl<T: Float> Callback<T> for Checkpoint {
+    fn on_epoch_end(&mut self, metrics: &EpochMetrics, _state: &mut TrainingState, network: &Network<T>) -> Result<(), NnError> {
+        if self.interval == 0 || !(metrics.epoch + 1).is_multiple_of(self.interval) {
+            return Ok(());
+        }
+        return network.save(self.path.replace("{epoch}", &metrics.epoch.to_string()));
+    }
+}
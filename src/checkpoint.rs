@@ -0,0 +1,135 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use rand_chacha::ChaCha12Rng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::NnError;
+use crate::float::Float;
+use crate::network::Network;
+use crate::trainer::History;
+
+/// The version of the checkpoint layout that the trainer writes.
+pub const CHECKPOINT_VERSION: u32 = 1;
+const PREFIX: &str = "checkpoint-";
+const EXTENSION: &str = ".json";
+
+/// Where and how often the trainer writes checkpoints.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointPolicy {
+    pub directory: PathBuf,
+    /// Writes a checkpoint after every `interval` epochs.
+    pub interval: usize,
+    /// Deletes all but the newest `keep_last` checkpoints. 0 keeps all of them.
+    pub keep_last: usize,
+}
+
+impl CheckpointPolicy {
+    pub fn new<P: AsRef<Path>>(directory: P, interval: usize, keep_last: usize) -> CheckpointPolicy {
+        CheckpointPolicy { directory: directory.as_ref().to_path_buf(), interval, keep_last }
+    }
+    /// The file of the checkpoint taken after `epoch`. The zero padding keeps
+    /// the names in epoch order.
+    pub fn path(&self, epoch: usize) -> PathBuf {
+        self.directory.join(format!("{}{:08}{}", PREFIX, epoch, EXTENSION))
+    }
+}
+
+/// Everything needed to continue a training run exactly where it stopped.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(serialize = "T: Float, O: Serialize", deserialize = "T: Float, O: DeserializeOwned"))]
+pub struct TrainingCheckpoint<T: Float, O> {
+    pub version: u32,
+    /// The first epoch that has not run yet.
+    pub epoch: usize,
+    pub step: u64,
+    pub learning_rate: f64,
+    pub network: Network<T>,
+    pub optimizer: O,
+    /// The state of the random number generator that shuffles the samples.
+    pub rng: ChaCha12Rng,
+    /// The state of every callback, in the order they were added to the trainer.
+    pub callbacks: Vec<Option<Value>>,
+    pub history: History,
+}
+
+impl<T: Float, O> TrainingCheckpoint<T, O> {
+    /// Whether the learning rate, every metric of the history and every
+    /// weight are finite. JSON has no room for the others, so a checkpoint
+    /// holding one could not be loaded again.
+    pub fn is_finite(&self) -> bool {
+        let metrics_finite = self.history.epochs.iter().all(|metrics| {
+            [metrics.learning_rate, metrics.train_loss, metrics.train_accuracy].iter()
+                .chain(metrics.validation_loss.iter())
+                .chain(metrics.validation_accuracy.iter())
+                .all(|value| value.is_finite())
+        });
+        let weights_finite = self.network.layers().iter()
+            .all(|layer| layer.weights.values().iter().chain(layer.biases.values()).all(|value| value.to_f64().is_finite()));
+        return self.learning_rate.is_finite() && metrics_finite && weights_finite;
+    }
+}
+
+impl<T: Float, O: Serialize> TrainingCheckpoint<T, O> {
+    /// Writes the checkpoint next to its destination first and then renames
+    /// it, so that a run killed while saving cannot leave a broken file behind.
+    /// A run that diverged is refused, see `is_finite`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), NnError> {
+        if !self.is_finite() {
+            return Err(NnError::Diverged { epoch: self.epoch.saturating_sub(1) });
+        }
+        let path = path.as_ref();
+        let temporary = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&temporary, path)?;
+        return Ok(());
+    }
+}
+
+impl<T: Float, O: DeserializeOwned> TrainingCheckpoint<T, O> {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<TrainingCheckpoint<T, O>, NnError> {
+        let value: Value = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+        if version != CHECKPOINT_VERSION as u64 {
+            return Err(NnError::UnsupportedVersion { found: version.min(u32::MAX as u64) as u32, supported: CHECKPOINT_VERSION });
+        }
+        return Ok(serde_json::from_value(value)?);
+    }
+}
+
+/// The checkpoints in a directory, oldest first.
+pub fn list<P: AsRef<Path>>(directory: P) -> Result<Vec<PathBuf>, NnError> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+        if name.starts_with(PREFIX) && name.ends_with(EXTENSION) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    return Ok(paths);
+}
+
+/// The newest checkpoint in a directory, if there is one.
+pub fn latest<P: AsRef<Path>>(directory: P) -> Result<Option<PathBuf>, NnError> {
+    return Ok(list(directory)?.pop());
+}
+
+/// Deletes all but the newest `keep_last` checkpoints of a directory.
+pub fn rotate<P: AsRef<Path>>(directory: P, keep_last: usize) -> Result<(), NnError> {
+    if keep_last == 0 {
+        return Ok(());
+    }
+    let paths = list(directory)?;
+    for path in paths.iter().take(paths.len().saturating_sub(keep_last)) {
+        fs::remove_file(path)?;
+    }
+    return Ok(());
+}
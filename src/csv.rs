@@ -0,0 +1,497 @@
+//! Loading tabular data from CSV files.
+//!
+//! A `CsvLoader` picks the feature and target columns of a file, fits a
+//! `Preprocessor` to them and turns every row into numbers: numeric columns
+//! are imputed and scaled, categorical columns are one-hot encoded. The fitted
+//! preprocessor is serializable, so it can be saved in the `ModelInfo` of the
+//! trained network and applied to new data in exactly the same way.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::dataset::{Dataset, InMemoryDataset};
+use crate::error::NnError;
+use crate::float::Float;
+use crate::matrix::Matrix;
+
+/// How missing values of numeric columns are filled in. Categorical columns
+/// always take their most frequent category.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Imputation {
+    Mean,
+    Median,
+    MostFrequent,
+    Constant { value: f64 },
+}
+
+/// How numeric feature columns are rescaled. Targets are never scaled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scaling {
+    None,
+    /// Into [0, 1] by the smallest and largest value of the column.
+    MinMax,
+    /// To a mean of 0 and a standard deviation of 1.
+    ZScore,
+}
+
+/// How the fields of a CSV file are laid out.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CsvFormat {
+    pub delimiter: char,
+    /// Whether the first record names the columns. Without a header the
+    /// columns are named by their position, starting at "0".
+    pub has_header: bool,
+    /// Fields that count as missing, besides empty ones.
+    pub missing: Vec<String>,
+}
+
+impl Default for CsvFormat {
+    fn default() -> Self {
+        CsvFormat { delimiter: ',', has_header: true, missing: vec!["NA".to_string(), "N/A".to_string(), "NaN".to_string(), "?".to_string()] }
+    }
+}
+
+/// How one column turns into numbers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Encoding {
+    /// One value, `(x - offset) / scale`, with missing values replaced by `fill` first.
+    Numeric { fill: f64, offset: f64, scale: f64 },
+    /// One value per category, 1 for the category of the field and 0 for the
+    /// others. Missing fields count as `fill`, categories not seen while
+    /// fitting are all zeros.
+    OneHot { categories: Vec<String>, fill: String },
+}
+
+/// The fitted encoding of a column, found by its name.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ColumnTransform {
+    pub name: String,
+    pub encoding: Encoding,
+}
+
+impl ColumnTransform {
+    /// The number of values the column turns into.
+    pub fn width(&self) -> usize {
+        match &self.encoding {
+            Encoding::Numeric { .. } => 1,
+            Encoding::OneHot { categories, .. } => categories.len(),
+        }
+    }
+    /// Appends the encoded field. A missing field is filled in, unless it is
+    /// `required`, as targets are.
+    fn encode(&self, field: Option<&str>, required: bool, values: &mut Vec<f64>) -> Result<(), NnError> {
+        if field.is_none() && required {
+            return Err(NnError::InvalidData(format!("the column \"{}\" has a missing value", self.name)));
+        }
+        match &self.encoding {
+            Encoding::Numeric { fill, offset, scale } => {
+                let value = match field {
+                    Some(field) => field.parse::<f64>()
+                        .map_err(|_| NnError::InvalidData(format!("\"{}\" in the column \"{}\" is not a number", field, self.name)))?,
+                    None => *fill,
+                };
+                values.push((value - offset) / scale);
+            }
+            Encoding::OneHot { categories, fill } => {
+                let category = field.unwrap_or(fill);
+                values.extend(categories.iter().map(|c| if c == category { 1.0 } else { 0.0 }));
+            }
+        }
+        return Ok(());
+    }
+}
+
+/// The fitted transform from the fields of a CSV file to the inputs and
+/// targets of a network.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Preprocessor {
+    pub format: CsvFormat,
+    pub features: Vec<ColumnTransform>,
+    pub targets: Vec<ColumnTransform>,
+}
+
+impl Preprocessor {
+    /// The number of inputs a network needs for the encoded features.
+    pub fn input_size(&self) -> usize {
+        self.features.iter().map(ColumnTransform::width).sum()
+    }
+    pub fn target_size(&self) -> usize {
+        self.targets.iter().map(ColumnTransform::width).sum()
+    }
+
+    /// Loads another file with the same columns, such as a test set.
+    pub fn load<T: Float, P: AsRef<Path>>(&self, path: P) -> Result<CsvDataset<T>, NnError> {
+        let table = Table::read(path, &self.format)?;
+        let inputs = self.encode_table(&table, &self.features, false)?;
+        let targets = self.encode_table(&table, &self.targets, true)?;
+        return Ok(CsvDataset { data: InMemoryDataset::new(inputs, targets)?, preprocessor: self.clone() });
+    }
+    /// Loads only the features of a file, which does not need the target columns.
+    pub fn load_features<T: Float, P: AsRef<Path>>(&self, path: P) -> Result<Matrix<T>, NnError> {
+        let table = Table::read(path, &self.format)?;
+        return self.encode_table(&table, &self.features, false);
+    }
+    /// Encodes the features of a single record whose fields are named by `names`.
+    pub fn encode_features<T: Float>(&self, names: &[&str], fields: &[&str]) -> Result<Vec<T>, NnError> {
+        if names.len() != fields.len() {
+            return Err(NnError::LengthMismatch { expected: names.len(), found: fields.len() });
+        }
+        let mut values = Vec::with_capacity(self.input_size());
+        for column in &self.features {
+            let i = names.iter().position(|name| *name == column.name)
+                .ok_or_else(|| NnError::InvalidData(format!("there is no column \"{}\"", column.name)))?;
+            column.encode(field_value(fields[i], &self.format), false, &mut values)?;
+        }
+        return Ok(values.into_iter().map(T::from_f64).collect());
+    }
+
+    fn encode_table<T: Float>(&self, table: &Table, columns: &[ColumnTransform], required: bool) -> Result<Matrix<T>, NnError> {
+        let indices = columns.iter().map(|column| table.column(&column.name)).collect::<Result<Vec<_>, _>>()?;
+        let width = columns.iter().map(ColumnTransform::width).sum();
+        let mut values = Vec::with_capacity(table.rows.len() * width);
+        for row in &table.rows {
+            for (column, &i) in columns.iter().zip(&indices) {
+                column.encode(field_value(&row[i], &self.format), required, &mut values)?;
+            }
+        }
+        return Matrix::from_flat(table.rows.len(), width, values.into_iter().map(T::from_f64).collect());
+    }
+}
+
+/// A CSV file as a `Dataset`, together with the preprocessor that encoded it.
+#[derive(Clone, Debug)]
+pub struct CsvDataset<T: Float = f32> {
+    data: InMemoryDataset<T>,
+    preprocessor: Preprocessor,
+}
+
+impl<T: Float> CsvDataset<T> {
+    pub fn inputs(&self) -> &Matrix<T> {
+        self.data.inputs()
+    }
+    pub fn targets(&self) -> &Matrix<T> {
+        self.data.targets()
+    }
+    pub fn preprocessor(&self) -> &Preprocessor {
+        &self.preprocessor
+    }
+}
+
+impl<T: Float> Dataset<T> for CsvDataset<T> {
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+    fn get(&self, i: usize) -> (Vec<T>, Vec<T>) {
+        self.data.get(i)
+    }
+}
+
+/// Fits a `Preprocessor` to a CSV file and loads the file with it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsvLoader {
+    pub format: CsvFormat,
+    /// The input columns. If empty, every column that is not a target.
+    pub features: Vec<String>,
+    pub targets: Vec<String>,
+    /// Columns to one-hot encode even though their values are numbers.
+    /// Columns with any value that is not a number are always categorical.
+    pub categorical: Vec<String>,
+    pub imputation: Imputation,
+    pub scaling: Scaling,
+}
+
+impl CsvLoader {
+    /// A loader for comma-separated files with a header that predicts the
+    /// `targets` from all other columns, imputing the mean and without scaling.
+    pub fn new(targets: &[&str]) -> CsvLoader {
+        CsvLoader {
+            format: CsvFormat::default(),
+            features: Vec::new(),
+            targets: targets.iter().map(|name| name.to_string()).collect(),
+            categorical: Vec::new(),
+            imputation: Imputation::Mean,
+            scaling: Scaling::None,
+        }
+    }
+    pub fn with_features(mut self, features: &[&str]) -> Self {
+        self.features = features.iter().map(|name| name.to_string()).collect();
+        self
+    }
+    pub fn with_categorical(mut self, categorical: &[&str]) -> Self {
+        self.categorical = categorical.iter().map(|name| name.to_string()).collect();
+        self
+    }
+    pub fn with_imputation(mut self, imputation: Imputation) -> Self {
+        self.imputation = imputation;
+        self
+    }
+    pub fn with_scaling(mut self, scaling: Scaling) -> Self {
+        self.scaling = scaling;
+        self
+    }
+    pub fn with_delimiter(mut self, delimiter: char) -> Self {
+        self.format.delimiter = delimiter;
+        self
+    }
+    pub fn with_header(mut self, has_header: bool) -> Self {
+        self.format.has_header = has_header;
+        self
+    }
+
+    /// Fits the preprocessor to the file and encodes the file with it.
+    pub fn load<T: Float, P: AsRef<Path>>(&self, path: P) -> Result<CsvDataset<T>, NnError> {
+        let table = Table::read(path, &self.format)?;
+        if self.targets.is_empty() {
+            return Err(NnError::InvalidData("no target columns were chosen".to_string()));
+        }
+        let features = if self.features.is_empty() {
+            table.names.iter().filter(|name| !self.targets.contains(name)).cloned().collect()
+        } else {
+            self.features.clone()
+        };
+        let preprocessor = Preprocessor {
+            format: self.format.clone(),
+            features: features.iter().map(|name| self.fit_column(&table, name, false)).collect::<Result<_, _>>()?,
+            targets: self.targets.iter().map(|name| self.fit_column(&table, name, true)).collect::<Result<_, _>>()?,
+        };
+        let inputs = preprocessor.encode_table(&table, &preprocessor.features, false)?;
+        let targets = preprocessor.encode_table(&table, &preprocessor.targets, true)?;
+        return Ok(CsvDataset { data: InMemoryDataset::new(inputs, targets)?, preprocessor });
+    }
+
+    fn fit_column(&self, table: &Table, name: &str, target: bool) -> Result<ColumnTransform, NnError> {
+        let i = table.column(name)?;
+        let fields: Vec<&str> = table.rows.iter().filter_map(|row| field_value(&row[i], &self.format)).collect();
+        if fields.is_empty() {
+            return Err(NnError::InvalidData(format!("the column \"{}\" has no values", name)));
+        }
+        let numbers: Option<Vec<f64>> = fields.iter().map(|field| field.parse::<f64>().ok()).collect();
+        let encoding = match numbers {
+            Some(numbers) if !self.categorical.iter().any(|c| c == name) => {
+                if target {
+                    Encoding::Numeric { fill: 0.0, offset: 0.0, scale: 1.0 }
+                } else {
+                    self.fit_numeric(numbers, table.rows.len())
+                }
+            }
+            _ => {
+                let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+                for field in &fields {
+                    *counts.entry(field).or_insert(0) += 1;
+                }
+                // The first of the most frequent categories, in sorted order.
+                let fill = counts.iter().rev().max_by_key(|(_, &count)| count).map(|(category, _)| category.to_string()).unwrap();
+                let categories: BTreeSet<&str> = fields.into_iter().collect();
+                Encoding::OneHot { categories: categories.into_iter().map(str::to_string).collect(), fill }
+            }
+        };
+        return Ok(ColumnTransform { name: name.to_string(), encoding });
+    }
+
+    /// The fill value and the scaling of a numeric feature column with `rows`
+    /// rows. The statistics for scaling include the filled-in values.
+    fn fit_numeric(&self, mut numbers: Vec<f64>, rows: usize) -> Encoding {
+        numbers.sort_by(|a, b| a.total_cmp(b));
+        let fill = match self.imputation {
+            Imputation::Mean => numbers.iter().sum::<f64>() / numbers.len() as f64,
+            Imputation::Median => {
+                let middle = numbers.len() / 2;
+                if numbers.len().is_multiple_of(2) { (numbers[middle - 1] + numbers[middle]) / 2.0 } else { numbers[middle] }
+            }
+            Imputation::MostFrequent => {
+                let mut best = (numbers[0], 0);
+                for run in numbers.chunk_by(|a, b| a == b) {
+                    if run.len() > best.1 {
+                        best = (run[0], run.len());
+                    }
+                }
+                best.0
+            }
+            Imputation::Constant { value } => value,
+        };
+        numbers.resize(rows, fill);
+        let (offset, scale) = match self.scaling {
+            Scaling::None => (0.0, 1.0),
+            Scaling::MinMax => {
+                let (min, max) = numbers.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &x| (min.min(x), max.max(x)));
+                (min, max - min)
+            }
+            Scaling::ZScore => {
+                let mean = numbers.iter().sum::<f64>() / rows as f64;
+                let variance = numbers.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / rows as f64;
+                (mean, variance.sqrt())
+            }
+        };
+        // A constant column keeps its offset but is not divided by zero.
+        let scale = if scale > 0.0 { scale } else { 1.0 };
+        return Encoding::Numeric { fill, offset, scale };
+    }
+}
+
+/// A field without surrounding whitespace, or `None` if it is missing.
+fn field_value<'a>(field: &'a str, format: &CsvFormat) -> Option<&'a str> {
+    let field = field.trim();
+    if field.is_empty() || format.missing.iter().any(|missing| missing == field) {
+        return None;
+    }
+    return Some(field);
+}
+
+/// The records of a CSV file under their column names.
+struct Table {
+    names: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn read<P: AsRef<Path>>(path: P, format: &CsvFormat) -> Result<Table, NnError> {
+        let mut records = parse(&fs::read_to_string(path)?, format.delimiter)?;
+        if records.is_empty() {
+            return Err(NnError::InvalidData("the CSV file is empty".to_string()));
+        }
+        let names = if format.has_header {
+            records.remove(0).iter().map(|name| name.trim().to_string()).collect()
+        } else {
+            (0..records[0].len()).map(|i| i.to_string()).collect::<Vec<_>>()
+        };
+        for (i, record) in records.iter().enumerate() {
+            if record.len() != names.len() {
+                return Err(NnError::InvalidData(format!(
+                    "record {} has {} fields, but there are {} columns",
+                    i + 1 + format.has_header as usize, record.len(), names.len())));
+            }
+        }
+        return Ok(Table { names, rows: records });
+    }
+    fn column(&self, name: &str) -> Result<usize, NnError> {
+        return self.names.iter().position(|column| column == name)
+            .ok_or_else(|| NnError::InvalidData(format!("there is no column \"{}\"", name)));
+    }
+}
+
+/// Splits CSV text into records of fields. Fields may be quoted to contain
+/// delimiters, line breaks and doubled quotes. Blank lines are skipped.
+fn parse(text: &str, delimiter: char) -> Result<Vec<Vec<String>>, NnError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    // Whether the current record has begun, which an empty quoted field
+    // counts for although it leaves the buffers empty.
+    let mut started = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            if c != '"' {
+                field.push(c);
+            } else if chars.peek() == Some(&'"') {
+                chars.next();
+                field.push('"');
+            } else {
+                quoted = false;
+            }
+        } else if c == '"' && field.trim().is_empty() {
+            field.clear();
+            quoted = true;
+            started = true;
+        } else if c == delimiter {
+            record.push(std::mem::take(&mut field));
+        } else if c == '\n' || c == '\r' {
+            if c == '\r' && chars.peek() == Some(&'\n') {
+                chars.next();
+            }
+            if started || !record.is_empty() || !field.is_empty() {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            started = false;
+        } else {
+            field.push(c);
+        }
+    }
+    if quoted {
+        return Err(NnError::InvalidData("the CSV file ends inside a quoted field".to_string()));
+    }
+    if started || !record.is_empty() || !field.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    return Ok(records);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `text` to a file of its own and loads it with `loader`.
+    fn load(name: &str, text: &str, loader: &CsvLoader) -> Result<CsvDataset<f64>, NnError> {
+        let path = std::env::temp_dir().join(format!("csv-test-{}-{}.csv", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        let dataset = loader.load(&path);
+        fs::remove_file(&path).unwrap();
+        return dataset;
+    }
+
+    fn fields(records: &[&[&str]]) -> Vec<Vec<String>> {
+        records.iter().map(|record| record.iter().map(|field| field.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn parses_quoted_fields() {
+        let text = "name,note\r\n\"Smith, J\",\"said \"\"hi\"\"\"\n\nplain,\"two\nlines\"\n\"\",\n";
+        assert_eq!(parse(text, ',').unwrap(), fields(&[
+            &["name", "note"],
+            &["Smith, J", "said \"hi\""],
+            &["plain", "two\nlines"],
+            &["", ""],
+        ]));
+        assert!(matches!(parse("a,\"open\n", ','), Err(NnError::InvalidData(_))));
+    }
+
+    #[test]
+    fn keeps_empty_quoted_records() {
+        assert_eq!(parse("label\n\"\"\nb\n\n\"\"", ',').unwrap(), fields(&[&["label"], &[""], &["b"], &[""]]));
+        let dataset = load("single", "x,y\n1,\"\"\n2,a\n3,a\n", &CsvLoader::new(&["x"])).unwrap();
+        assert_eq!(dataset.len(), 3);
+        assert_eq!(dataset.inputs().values(), &[1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn imputes_missing_values() {
+        // The second row misses `a`, the third the category `b`, which becomes the most frequent one, x.
+        let text = "a;b;c\n1;x;5\nNA;y;6\n4;;5\n7;x;1\n";
+        let inputs = |imputation: Imputation| -> Vec<f64> {
+            let loader = CsvLoader::new(&["c"]).with_delimiter(';').with_imputation(imputation);
+            return load("impute", text, &loader).unwrap().inputs().values().to_vec();
+        };
+        assert_eq!(inputs(Imputation::Mean), vec![1.0, 1.0, 0.0, 4.0, 0.0, 1.0, 4.0, 1.0, 0.0, 7.0, 1.0, 0.0]);
+        assert_eq!(inputs(Imputation::Median)[3], 4.0);
+        assert_eq!(inputs(Imputation::MostFrequent)[3], 1.0);
+        assert_eq!(inputs(Imputation::Constant { value: -1.0 })[3], -1.0);
+        // Targets are not imputed.
+        let missing_target = load("target", "a;c\n1;5\n2;?\n", &CsvLoader::new(&["c"]).with_delimiter(';'));
+        assert!(matches!(missing_target, Err(NnError::InvalidData(_))));
+    }
+
+    #[test]
+    fn scales_the_imputed_features() {
+        let text = "a,y\n0,1\n,0\n10,1\n2,0\n";
+        let min_max = load("min-max", text, &CsvLoader::new(&["y"]).with_scaling(Scaling::MinMax)).unwrap();
+        assert_eq!(min_max.inputs().values(), &[0.0, 0.4, 1.0, 0.2]);
+        assert_eq!(min_max.targets().values(), &[1.0, 0.0, 1.0, 0.0]);
+        let z_score = load("z-score", text, &CsvLoader::new(&["y"]).with_scaling(Scaling::ZScore)).unwrap();
+        let values = z_score.inputs().values();
+        let mean = values.iter().sum::<f64>() / 4.0;
+        let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / 4.0;
+        assert!(mean.abs() < 1e-12 && (variance - 1.0).abs() < 1e-12);
+        let preprocessor = z_score.preprocessor();
+        let encoded = preprocessor.encode_features::<f64>(&["y", "a"], &["1", ""]).unwrap();
+        assert!((encoded[0] - values[1]).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,298 @@
+use std::panic;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use rand::seq::SliceRandom;
+use rand::RngCore;
+
+use crate::error::NnError;
+use crate::float::Float;
+use crate::matrix::Matrix;
+
+/// A source of samples that can be read in any order.
+pub trait Dataset<T: Float>: Send + Sync {
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The input and the target of sample `i`. Every sample of a data set has
+    /// inputs and targets of the same lengths.
+    fn get(&self, i: usize) -> (Vec<T>, Vec<T>);
+}
+
+/// A data set held in two matrices with one sample per row.
+#[derive(Clone, Debug)]
+pub struct InMemoryDataset<T: Float = f32> {
+    inputs: Matrix<T>,
+    targets: Matrix<T>,
+}
+
+impl<T: Float> InMemoryDataset<T> {
+    pub fn new(inputs: Matrix<T>, targets: Matrix<T>) -> Result<InMemoryDataset<T>, NnError> {
+        if inputs.rows() != targets.rows() {
+            return Err(NnError::ShapeMismatch { op: "pair", left: inputs.shape(), right: targets.shape() });
+        }
+        return Ok(InMemoryDataset { inputs, targets });
+    }
+    pub fn inputs(&self) -> &Matrix<T> {
+        &self.inputs
+    }
+    pub fn targets(&self) -> &Matrix<T> {
+        &self.targets
+    }
+}
+
+impl<T: Float> Dataset<T> for InMemoryDataset<T> {
+    fn len(&self) -> usize {
+        self.inputs.rows()
+    }
+    fn get(&self, i: usize) -> (Vec<T>, Vec<T>) {
+        (self.inputs.row(i).to_vec(), self.targets.row(i).to_vec())
+    }
+}
+
+/// One mini-batch: the inputs and the targets of its samples, one per row.
+pub type Batch<T> = (Matrix<T>, Matrix<T>);
+
+/// Stacks the given samples of a data set into a batch.
+fn collate<T: Float>(dataset: &dyn Dataset<T>, indices: &[usize]) -> Result<Batch<T>, NnError> {
+    let mut inputs = Vec::new();
+    let mut targets = Vec::new();
+    let mut sizes = None;
+    for &i in indices {
+        let (input, target) = dataset.get(i);
+        let expected = *sizes.get_or_insert((input.len(), target.len()));
+        if expected.0 != input.len() {
+            return Err(NnError::LengthMismatch { expected: expected.0, found: input.len() });
+        }
+        if expected.1 != target.len() {
+            return Err(NnError::LengthMismatch { expected: expected.1, found: target.len() });
+        }
+        inputs.extend(input);
+        targets.extend(target);
+    }
+    let (input_size, target_size) = sizes.ok_or(NnError::EmptyDimension)?;
+    return Ok((Matrix::from_flat(indices.len(), input_size, inputs)?, Matrix::from_flat(indices.len(), target_size, targets)?));
+}
+
+/// Cuts a data set into mini-batches, in a new order every epoch if `shuffle`
+/// is set. With `workers` above zero, that many threads assemble the
+/// upcoming batches in the background while the current one trains.
+pub struct DataLoader<T: Float, D: Dataset<T>> {
+    dataset: Arc<D>,
+    pub batch_size: usize,
+    pub shuffle: bool,
+    /// Leaves out the last batch of an epoch if it is smaller than `batch_size`.
+    pub drop_last: bool,
+    pub workers: usize,
+    /// How many finished batches every worker may hold ahead of training.
+    pub prefetch: usize,
+    marker: std::marker::PhantomData<fn() -> T>,
+}
+
+impl<T: Float, D: Dataset<T> + 'static> DataLoader<T, D> {
+    /// A loader that shuffles batches of 32 samples on the calling thread.
+    pub fn new(dataset: D) -> DataLoader<T, D> {
+        DataLoader::from_shared(Arc::new(dataset))
+    }
+    /// A loader for a data set that is also used elsewhere.
+    pub fn from_shared(dataset: Arc<D>) -> DataLoader<T, D> {
+        DataLoader { dataset, batch_size: 32, shuffle: true, drop_last: false, workers: 0, prefetch: 2, marker: std::marker::PhantomData }
+    }
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+    pub fn with_shuffle(mut self, shuffle: bool) -> Self {
+        self.shuffle = shuffle;
+        self
+    }
+    pub fn with_drop_last(mut self, drop_last: bool) -> Self {
+        self.drop_last = drop_last;
+        self
+    }
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+    pub fn dataset(&self) -> &Arc<D> {
+        &self.dataset
+    }
+    /// The number of batches in one epoch.
+    pub fn len(&self) -> usize {
+        let len = self.dataset.len();
+        if self.batch_size == 0 {
+            return 0;
+        }
+        return if self.drop_last { len / self.batch_size } else { len.div_ceil(self.batch_size) };
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The batches of one epoch. The generator only decides the order of the
+    /// samples, so a seeded generator gives the same batches every time.
+    pub fn epoch(&self, rng: &mut dyn RngCore) -> Result<Batches<T>, NnError> {
+        if self.batch_size == 0 {
+            return Err(NnError::EmptyDimension);
+        }
+        let mut order: Vec<usize> = (0..self.dataset.len()).collect();
+        if self.shuffle {
+            order.shuffle(rng);
+        }
+        let batches: Vec<Vec<usize>> = order.chunks(self.batch_size)
+            .filter(|batch| !self.drop_last || batch.len() == self.batch_size)
+            .map(|batch| batch.to_vec())
+            .collect();
+        if self.workers == 0 {
+            let dataset: Arc<dyn Dataset<T>> = self.dataset.clone();
+            return Ok(Batches { source: Source::Inline { dataset, batches: batches.into_iter() } });
+        }
+        // Worker w assembles batches w, w + workers, ..., so reading the
+        // workers in turn gives the batches back in order.
+        let workers = self.workers.min(batches.len().max(1));
+        let mut receivers = Vec::with_capacity(workers);
+        let mut handles = Vec::with_capacity(workers);
+        for worker in 0..workers {
+            let (sender, receiver) = mpsc::sync_channel(self.prefetch.max(1));
+            let dataset = self.dataset.clone();
+            let assigned: Vec<Vec<usize>> = batches.iter().skip(worker).step_by(workers).cloned().collect();
+            handles.push(Some(thread::spawn(move || {
+                for batch in assigned {
+                    if sender.send(collate(dataset.as_ref(), &batch)).is_err() {
+                        return;
+                    }
+                }
+            })));
+            receivers.push(receiver);
+        }
+        return Ok(Batches { source: Source::Workers { receivers, handles, next: 0, remaining: batches.len() } });
+    }
+}
+
+enum Source<T: Float> {
+    Inline {
+        dataset: Arc<dyn Dataset<T>>,
+        batches: std::vec::IntoIter<Vec<usize>>,
+    },
+    Workers {
+        receivers: Vec<Receiver<Result<Batch<T>, NnError>>>,
+        handles: Vec<Option<JoinHandle<()>>>,
+        next: usize,
+        remaining: usize,
+    },
+}
+
+/// The batches of one epoch of a `DataLoader`, in order.
+pub struct Batches<T: Float> {
+    source: Source<T>,
+}
+
+impl<T: Float> Iterator for Batches<T> {
+    type Item = Result<Batch<T>, NnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.source {
+            Source::Inline { dataset, batches } => {
+                let batch = batches.next()?;
+                return Some(collate(dataset.as_ref(), &batch));
+            }
+            Source::Workers { receivers, handles, next, remaining } => {
+                if *remaining == 0 {
+                    return None;
+                }
+                let worker = *next % receivers.len();
+                let received = match receivers[worker].recv() {
+                    Ok(received) => received,
+                    Err(_) => {
+                        // The worker hung up before sending all of its batches,
+                        // which only happens if it panicked. Panic here as well,
+                        // as reading the data set on this thread would have.
+                        *remaining = 0;
+                        if let Some(Err(payload)) = handles[worker].take().map(JoinHandle::join) {
+                            panic::resume_unwind(payload);
+                        }
+                        return Some(Err(NnError::InvalidData("a loader worker stopped before its last batch".to_string())));
+                    }
+                };
+                *next += 1;
+                *remaining -= 1;
+                return Some(received);
+            }
+        }
+    }
+}
+
+impl<T: Float> Drop for Batches<T> {
+    /// Stops the workers of an epoch that was not read to the end, and passes
+    /// on a panic of one of them unless this thread is panicking already.
+    fn drop(&mut self) {
+        if let Source::Workers { receivers, handles, .. } = &mut self.source {
+            receivers.clear();
+            for handle in handles.drain(..).flatten() {
+                if let Err(payload) = handle.join() {
+                    if !thread::panicking() {
+                        panic::resume_unwind(payload);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    /// Sample `i` is `[i]` with the target `[2i]`, and reading `panic_at` panics.
+    struct Counting {
+        len: usize,
+        panic_at: Option<usize>,
+    }
+
+    impl Dataset<f64> for Counting {
+        fn len(&self) -> usize {
+            self.len
+        }
+        fn get(&self, i: usize) -> (Vec<f64>, Vec<f64>) {
+            assert_ne!(Some(i), self.panic_at, "sample {} cannot be read", i);
+            (vec![i as f64], vec![2.0 * i as f64])
+        }
+    }
+
+    fn epoch(workers: usize, drop_last: bool) -> Vec<Vec<f64>> {
+        let loader = DataLoader::new(Counting { len: 23, panic_at: None }).with_batch_size(5).with_drop_last(drop_last).with_workers(workers);
+        return loader.epoch(&mut ChaCha12Rng::seed_from_u64(4)).unwrap()
+            .map(|batch| {
+                let (inputs, targets) = batch.unwrap();
+                assert_eq!(targets.values(), inputs.values().iter().map(|x| 2.0 * x).collect::<Vec<_>>().as_slice());
+                inputs.values().to_vec()
+            })
+            .collect();
+    }
+
+    #[test]
+    fn workers_give_the_same_batches_in_the_same_order() {
+        let inline = epoch(0, false);
+        assert_eq!(inline.iter().map(Vec::len).collect::<Vec<_>>(), vec![5, 5, 5, 5, 3]);
+        let mut samples: Vec<f64> = inline.concat();
+        samples.sort_by(f64::total_cmp);
+        assert_eq!(samples, (0..23).map(|i| i as f64).collect::<Vec<_>>());
+        for workers in 1..4 {
+            assert_eq!(epoch(workers, false), inline);
+        }
+        assert_eq!(epoch(2, true), inline[..4].to_vec());
+    }
+
+    #[test]
+    #[should_panic(expected = "sample 7 cannot be read")]
+    fn a_panicking_worker_is_not_a_short_epoch() {
+        let loader = DataLoader::new(Counting { len: 23, panic_at: Some(7) }).with_batch_size(5).with_shuffle(false).with_workers(2);
+        for batch in loader.epoch(&mut ChaCha12Rng::seed_from_u64(4)).unwrap() {
+            batch.unwrap();
+        }
+    }
+}
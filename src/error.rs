@@ -0,0 +1,92 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
+
+use crate::float::Precision;
+
+/// Everything that can go wrong while building, running or loading a network.
+#[derive(Debug)]
+pub enum NnError {
+    /// The operands of `op` have shapes that do not fit together.
+    ShapeMismatch {
+        op: &'static str,
+        left: (usize, usize),
+        right: (usize, usize),
+    },
+    /// A matrix was requested with zero rows or columns.
+    EmptyDimension,
+    /// The rows given to build a matrix do not all have the same length.
+    RaggedRows,
+    /// The number of values does not match the dimensions of a matrix.
+    LengthMismatch { expected: usize, found: usize },
+    /// A row was requested that the matrix does not have.
+    RowOutOfRange { row: usize, rows: usize },
+    /// A network was saved with a different precision than it is loaded as.
+    PrecisionMismatch { expected: Precision, found: Precision },
+    /// A data file started with the wrong magic number.
+    BadMagic { expected: u32, found: u32 },
+    /// A data file is malformed.
+    InvalidData(String),
+    /// A label does not fit into the number of classes.
+    LabelOutOfRange { label: usize, classes: usize },
+    /// A model file was written by a newer version of the format than this library reads.
+    UnsupportedVersion { found: u32, supported: u32 },
+    /// A model file is readable but does not describe a consistent network.
+    InvalidModel(String),
+    /// Training reached a loss, learning rate or weight that is not a finite
+    /// number, so the run cannot be checkpointed after `epoch`.
+    Diverged { epoch: usize },
+    Io(io::Error),
+    Json(serde_json::Error),
+}
+
+impl Display for NnError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NnError::ShapeMismatch { op, left, right } => write!(
+                f, "Cannot {} a {}x{} and a {}x{} matrix.", op, left.0, left.1, right.0, right.1),
+            NnError::EmptyDimension => write!(f, "The dimensions may not be 0."),
+            NnError::RaggedRows => write!(f, "The rows of the matrix do not all have the same length."),
+            NnError::LengthMismatch { expected, found } => write!(
+                f, "Expected {} values for the matrix but found {}.", expected, found),
+            NnError::RowOutOfRange { row, rows } => write!(
+                f, "Cannot select row {} of a matrix with {} rows.", row, rows),
+            NnError::PrecisionMismatch { expected, found } => write!(
+                f, "The network was saved with {:?} precision but is being loaded as {:?}.", found, expected),
+            NnError::BadMagic { expected, found } => write!(
+                f, "Expected the magic number {:#010x} but found {:#010x}.", expected, found),
+            NnError::InvalidData(reason) => write!(f, "Invalid data file: {}.", reason),
+            NnError::LabelOutOfRange { label, classes } => write!(
+                f, "The label {} does not fit into {} classes.", label, classes),
+            NnError::UnsupportedVersion { found, supported } => write!(
+                f, "The model file has format version {} but only versions up to {} are supported.", found, supported),
+            NnError::InvalidModel(reason) => write!(f, "Invalid model file: {}.", reason),
+            NnError::Diverged { epoch } => write!(
+                f, "Training diverged by epoch {}: a loss, the learning rate or a weight is not finite.", epoch),
+            NnError::Io(error) => write!(f, "{}", error),
+            NnError::Json(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for NnError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NnError::Io(error) => Some(error),
+            NnError::Json(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for NnError {
+    fn from(error: io::Error) -> Self {
+        NnError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for NnError {
+    fn from(error: serde_json::Error) -> Self {
+        NnError::Json(error)
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt::{Debug, Display};
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// The floating point precision a network was built with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Precision {
+    F32,
+    F64,
+}
+
+/// The element type of a `Matrix`. Implemented for `f32` and `f64`.
+pub trait Float:
+    Copy
+    + Debug
+    + Display
+    + Default
+    + PartialOrd
+    + Send
+    + Sync
+    + Serialize
+    + DeserializeOwned
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + DivAssign
+    + Sum
+    + 'static
+{
+    const PRECISION: Precision;
+
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn from_f32(value: f32) -> Self;
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn from_usize(value: usize) -> Self;
+
+    fn exp(self) -> Self;
+    fn ln(self) -> Self;
+    fn tanh(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn abs(self) -> Self;
+    fn powi(self, n: i32) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn is_finite(self) -> bool;
+}
+
+macro_rules! impl_float {
+    ($t:ident, $precision:expr) => {
+        impl Float for $t {
+            const PRECISION: Precision = $precision;
+
+            #[inline]
+            fn zero() -> Self { 0.0 }
+            #[inline]
+            fn one() -> Self { 1.0 }
+            #[inline]
+            fn from_f32(value: f32) -> Self { value as $t }
+            #[inline]
+            fn from_f64(value: f64) -> Self { value as $t }
+            #[inline]
+            fn to_f64(self) -> f64 { self as f64 }
+            #[inline]
+            fn from_usize(value: usize) -> Self { value as $t }
+
+            #[inline]
+            fn exp(self) -> Self { $t::exp(self) }
+            #[inline]
+            fn ln(self) -> Self { $t::ln(self) }
+            #[inline]
+            fn tanh(self) -> Self { $t::tanh(self) }
+            #[inline]
+            fn sqrt(self) -> Self { $t::sqrt(self) }
+            #[inline]
+            fn abs(self) -> Self { $t::abs(self) }
+            #[inline]
+            fn powi(self, n: i32) -> Self { $t::powi(self, n) }
+            #[inline]
+            fn max(self, other: Self) -> Self { $t::max(self, other) }
+            #[inline]
+            fn min(self, other: Self) -> Self { $t::min(self, other) }
+            #[inline]
+            fn is_finite(self) -> bool { $t::is_finite(self) }
+        }
+    };
+}
+
+impl_float!(f32, Precision::F32);
+impl_float!(f64, Precision::F64);
@@ -0,0 +1,195 @@
+use std::thread;
+
+use crate::float::Float;
+
+/// Rows of the right operand that are streamed through the cache at once.
+const BLOCK_DEPTH: usize = 256;
+/// Columns of the output that are updated per tile.
+const BLOCK_COLS: usize = 512;
+/// Rows of a transposed right operand whose dot products share a tile.
+const BLOCK_DOT: usize = 64;
+/// Products with fewer multiply-adds than this are not worth spawning threads for.
+const PARALLEL_THRESHOLD: usize = 1 << 20;
+
+/// A read-only, strided view of a row-major buffer. A transposed matrix is the
+/// same buffer with the two strides swapped, so no copy is ever made.
+#[derive(Clone, Copy)]
+pub(crate) struct View<'a, T> {
+    values: &'a [T],
+    row_stride: usize,
+    col_stride: usize,
+}
+
+impl<'a, T: Float> View<'a, T> {
+    pub(crate) fn new(values: &'a [T], cols: usize) -> View<'a, T> {
+        View { values, row_stride: cols, col_stride: 1 }
+    }
+    pub(crate) fn transposed(values: &'a [T], cols: usize) -> View<'a, T> {
+        View { values, row_stride: 1, col_stride: cols }
+    }
+    #[inline]
+    fn at(&self, row: usize, col: usize) -> T {
+        self.values[row * self.row_stride + col * self.col_stride]
+    }
+    /// The contiguous slice `row[start..start + len]`. Only valid if rows are contiguous.
+    #[inline]
+    fn row_slice(&self, row: usize, start: usize, len: usize) -> &'a [T] {
+        debug_assert_eq!(self.col_stride, 1);
+        let begin = row * self.row_stride + start;
+        &self.values[begin..begin + len]
+    }
+    /// The contiguous slice `col[start..start + len]`. Only valid if columns are contiguous.
+    #[inline]
+    fn col_slice(&self, col: usize, start: usize, len: usize) -> &'a [T] {
+        debug_assert_eq!(self.row_stride, 1);
+        let begin = col * self.col_stride + start;
+        &self.values[begin..begin + len]
+    }
+}
+
+/// Computes the `m x n` product of the `m x k` operand `a` and the `k x n` operand `b`.
+///
+/// The output rows are split evenly across the available cores. Either `b` has
+/// contiguous rows, or it is a transposed view and `a` has contiguous rows.
+pub(crate) fn multiply<T: Float>(a: View<T>, b: View<T>, m: usize, k: usize, n: usize) -> Vec<T> {
+    let mut out = vec![T::zero(); m * n];
+    let threads = thread_count(m, k, n);
+    if threads <= 1 {
+        kernel(a, b, 0, &mut out, k, n);
+    } else {
+        let rows_per_thread = m.div_ceil(threads);
+        thread::scope(|scope| {
+            for (chunk_index, chunk) in out.chunks_mut(rows_per_thread * n).enumerate() {
+                scope.spawn(move || kernel(a, b, chunk_index * rows_per_thread, chunk, k, n));
+            }
+        });
+    }
+    return out;
+}
+
+/// The reference i-j-k product, used to validate the blocked kernels.
+pub(crate) fn multiply_naive<T: Float>(a: View<T>, b: View<T>, m: usize, k: usize, n: usize) -> Vec<T> {
+    let mut out = vec![T::zero(); m * n];
+    for i in 0..m {
+        for j in 0..n {
+            for kk in 0..k {
+                out[i * n + j] += a.at(i, kk) * b.at(kk, j);
+            }
+        }
+    }
+    return out;
+}
+
+fn thread_count(m: usize, k: usize, n: usize) -> usize {
+    if m < 2 || m.saturating_mul(k).saturating_mul(n) < PARALLEL_THRESHOLD {
+        return 1;
+    }
+    let cores = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    return cores.min(m);
+}
+
+/// Fills `out`, which holds the output rows starting at `row_start`.
+fn kernel<T: Float>(a: View<T>, b: View<T>, row_start: usize, out: &mut [T], k: usize, n: usize) {
+    if b.col_stride == 1 {
+        kernel_rows(a, b, row_start, out, k, n);
+    } else {
+        kernel_dot(a, b, row_start, out, k, n);
+    }
+}
+
+/// Accumulates scaled rows of `b` into each output row, tiled so that a block of
+/// `b` stays in cache while every output row in the chunk is updated.
+fn kernel_rows<T: Float>(a: View<T>, b: View<T>, row_start: usize, out: &mut [T], k: usize, n: usize) {
+    let rows = out.len() / n;
+    for depth_start in (0..k).step_by(BLOCK_DEPTH) {
+        let depth_end = (depth_start + BLOCK_DEPTH).min(k);
+        for col_start in (0..n).step_by(BLOCK_COLS) {
+            let cols = (col_start + BLOCK_COLS).min(n) - col_start;
+            for i in 0..rows {
+                let out_row = &mut out[i * n + col_start..i * n + col_start + cols];
+                for kk in depth_start..depth_end {
+                    let scale = a.at(row_start + i, kk);
+                    for (x, y) in out_row.iter_mut().zip(b.row_slice(kk, col_start, cols)) {
+                        *x += scale * *y;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Computes each output as the dot product of a row of `a` with a row of the
+/// transposed `b`, reusing a block of `b` for every output row in the chunk.
+fn kernel_dot<T: Float>(a: View<T>, b: View<T>, row_start: usize, out: &mut [T], k: usize, n: usize) {
+    let rows = out.len() / n;
+    for col_start in (0..n).step_by(BLOCK_DOT) {
+        let col_end = (col_start + BLOCK_DOT).min(n);
+        for i in 0..rows {
+            let a_row = a.row_slice(row_start + i, 0, k);
+            for j in col_start..col_end {
+                out[i * n + j] = dot(a_row, b.col_slice(j, 0, k));
+            }
+        }
+    }
+}
+
+/// A dot product over independent lanes, which lets the compiler vectorize the sum.
+#[inline]
+fn dot<T: Float>(a: &[T], b: &[T]) -> T {
+    const LANES: usize = 8;
+    let mut sums = [T::zero(); LANES];
+    let a_chunks = a.chunks_exact(LANES);
+    let b_chunks = b.chunks_exact(LANES);
+    let tail: T = a_chunks.remainder().iter().zip(b_chunks.remainder()).map(|(&x, &y)| x * y).sum();
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for lane in 0..LANES {
+            sums[lane] += x[lane] * y[lane];
+        }
+    }
+    return sums.iter().copied().sum::<T>() + tail;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A deterministic `rows x cols` buffer with values in [-1, 1).
+    fn values(rows: usize, cols: usize, seed: usize) -> Vec<f32> {
+        (0..rows * cols).map(|i| ((i * 7919 + seed * 104729) % 2003) as f32 / 1001.5 - 1.0).collect()
+    }
+
+    fn assert_close(fast: &[f32], naive: &[f32]) {
+        assert_eq!(fast.len(), naive.len());
+        for (x, y) in fast.iter().zip(naive) {
+            assert!((x - y).abs() < 1e-3, "{} != {}", x, y);
+        }
+    }
+
+    /// Checks both kernels on an `m x k` times `k x n` product.
+    fn check(m: usize, k: usize, n: usize) {
+        let a = values(m, k, 1);
+        let b = values(k, n, 2);
+        let expected = multiply_naive(View::new(&a, k), View::new(&b, n), m, k, n);
+        assert_close(&multiply(View::new(&a, k), View::new(&b, n), m, k, n), &expected);
+
+        // The same right operand, stored as its `n x k` transpose.
+        let b_t: Vec<f32> = (0..n * k).map(|i| b[(i % k) * n + i / k]).collect();
+        assert_close(&multiply(View::new(&a, k), View::transposed(&b_t, k), m, k, n), &expected);
+    }
+
+    #[test]
+    fn blocked_kernels_match_the_naive_product() {
+        check(1, 1, 1);
+        check(1, 300, 70);
+        check(3, BLOCK_DEPTH + 1, BLOCK_COLS + 3);
+        check(5, 2 * BLOCK_DEPTH - 1, BLOCK_DOT + 1);
+        check(7, 9, 2 * BLOCK_DOT + 5);
+    }
+
+    #[test]
+    fn threaded_products_match_the_naive_product() {
+        let (m, k, n) = (67, 130, 129);
+        assert!(m * k * n >= PARALLEL_THRESHOLD);
+        check(m, k, n);
+    }
+}
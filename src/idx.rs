@@ -0,0 +1,244 @@
+//! Reading and writing files in the IDX format of the MNIST data set.
+//!
+//! An IDX file starts with two zero bytes, a byte for the type of the values
+//! and a byte for the number of dimensions. The size of every dimension
+//! follows as a big-endian `u32`, and then the values themselves, big-endian
+//! and in row-major order.
+
+use std::fs;
+use std::path::Path;
+
+use crate::error::NnError;
+use crate::float::Float;
+use crate::matrix::Matrix;
+
+/// The type of the values of an IDX file, named by the byte in its magic number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdxType {
+    U8,
+    I8,
+    I16,
+    I32,
+    F32,
+    F64,
+}
+
+impl IdxType {
+    pub fn from_code(code: u8) -> Option<IdxType> {
+        match code {
+            0x08 => Some(IdxType::U8),
+            0x09 => Some(IdxType::I8),
+            0x0B => Some(IdxType::I16),
+            0x0C => Some(IdxType::I32),
+            0x0D => Some(IdxType::F32),
+            0x0E => Some(IdxType::F64),
+            _ => None,
+        }
+    }
+    pub fn code(&self) -> u8 {
+        match self {
+            IdxType::U8 => 0x08,
+            IdxType::I8 => 0x09,
+            IdxType::I16 => 0x0B,
+            IdxType::I32 => 0x0C,
+            IdxType::F32 => 0x0D,
+            IdxType::F64 => 0x0E,
+        }
+    }
+    /// The number of bytes of one value.
+    pub fn size(&self) -> usize {
+        match self {
+            IdxType::U8 | IdxType::I8 => 1,
+            IdxType::I16 => 2,
+            IdxType::I32 | IdxType::F32 => 4,
+            IdxType::F64 => 8,
+        }
+    }
+    fn decode(&self, bytes: &[u8]) -> f64 {
+        match self {
+            IdxType::U8 => bytes[0] as f64,
+            IdxType::I8 => bytes[0] as i8 as f64,
+            IdxType::I16 => i16::from_be_bytes([bytes[0], bytes[1]]) as f64,
+            IdxType::I32 => i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            IdxType::F32 => f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            IdxType::F64 => f64::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]),
+        }
+    }
+    /// Appends a value, rounded and saturated to the range of integer types.
+    fn encode(&self, value: f64, bytes: &mut Vec<u8>) {
+        match self {
+            IdxType::U8 => bytes.push(value.round() as u8),
+            IdxType::I8 => bytes.push(value.round() as i8 as u8),
+            IdxType::I16 => bytes.extend_from_slice(&(value.round() as i16).to_be_bytes()),
+            IdxType::I32 => bytes.extend_from_slice(&(value.round() as i32).to_be_bytes()),
+            IdxType::F32 => bytes.extend_from_slice(&(value as f32).to_be_bytes()),
+            IdxType::F64 => bytes.extend_from_slice(&value.to_be_bytes()),
+        }
+    }
+}
+
+/// The contents of an IDX file. The values are kept as the raw big-endian
+/// bytes of the file and only converted when they are read, so an array takes
+/// no more memory than its file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IdxArray {
+    pub data_type: IdxType,
+    pub dimensions: Vec<usize>,
+    data: Vec<u8>,
+}
+
+impl IdxArray {
+    /// An array of the given shape, checking that the values fill it exactly.
+    /// Values are rounded and saturated to the range of integer types.
+    pub fn new(data_type: IdxType, dimensions: Vec<usize>, values: Vec<f64>) -> Result<IdxArray, NnError> {
+        let mut data = Vec::with_capacity(values.len() * data_type.size());
+        for value in values {
+            data_type.encode(value, &mut data);
+        }
+        return IdxArray::from_data(data_type, dimensions, data);
+    }
+    /// An array of the given shape from values already encoded as in a file.
+    pub fn from_data(data_type: IdxType, dimensions: Vec<usize>, data: Vec<u8>) -> Result<IdxArray, NnError> {
+        if dimensions.is_empty() || dimensions.len() > u8::MAX as usize {
+            return Err(NnError::InvalidData(format!("an IDX file has between 1 and 255 dimensions, not {}", dimensions.len())));
+        }
+        let expected = dimensions.iter().try_fold(1usize, |product, &size| product.checked_mul(size))
+            .and_then(|count| count.checked_mul(data_type.size()))
+            .ok_or_else(|| NnError::InvalidData("the IDX dimensions are too large".to_string()))?;
+        if data.len() != expected {
+            return Err(NnError::LengthMismatch { expected, found: data.len() });
+        }
+        return Ok(IdxArray { data_type, dimensions, data });
+    }
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<IdxArray, NnError> {
+        return IdxArray::decode_owned(fs::read(path)?);
+    }
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), NnError> {
+        fs::write(path, self.encode()?)?;
+        return Ok(());
+    }
+
+    /// Parses an IDX file, checking that its size matches its header.
+    pub fn decode(bytes: &[u8]) -> Result<IdxArray, NnError> {
+        return IdxArray::decode_owned(bytes.to_vec());
+    }
+    /// Like `decode`, reusing the buffer of the file for the values.
+    fn decode_owned(mut bytes: Vec<u8>) -> Result<IdxArray, NnError> {
+        if bytes.len() < 4 {
+            return Err(NnError::InvalidData("the file is too short for an IDX header".to_string()));
+        }
+        if bytes[0] != 0 || bytes[1] != 0 {
+            return Err(NnError::InvalidData("an IDX file starts with two zero bytes".to_string()));
+        }
+        let data_type = IdxType::from_code(bytes[2])
+            .ok_or_else(|| NnError::InvalidData(format!("unknown IDX data type {:#04x}", bytes[2])))?;
+        let dimension_count = bytes[3] as usize;
+        let header_size = 4 + 4 * dimension_count;
+        if bytes.len() < header_size {
+            return Err(NnError::InvalidData("the file is too short for its IDX header".to_string()));
+        }
+        let dimensions: Vec<usize> = bytes[4..header_size].chunks(4)
+            .map(|size| u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize)
+            .collect();
+        let count = dimensions.iter().try_fold(1usize, |product, &size| product.checked_mul(size))
+            .ok_or_else(|| NnError::InvalidData("the IDX dimensions are too large".to_string()))?;
+        let data_size = bytes.len() - header_size;
+        if count.checked_mul(data_type.size()) != Some(data_size) {
+            return Err(NnError::InvalidData(format!(
+                "the IDX header describes {} values of {} bytes, but the file holds {} bytes of data",
+                count, data_type.size(), data_size)));
+        }
+        bytes.drain(..header_size);
+        return IdxArray::from_data(data_type, dimensions, bytes);
+    }
+    pub fn encode(&self) -> Result<Vec<u8>, NnError> {
+        let mut bytes = Vec::with_capacity(4 + 4 * self.dimensions.len() + self.data.len());
+        bytes.extend_from_slice(&[0, 0, self.data_type.code(), self.dimensions.len() as u8]);
+        for &size in &self.dimensions {
+            if size > u32::MAX as usize {
+                return Err(NnError::InvalidData(format!("the IDX dimension {} does not fit into 32 bits", size)));
+            }
+            bytes.extend_from_slice(&(size as u32).to_be_bytes());
+        }
+        bytes.extend_from_slice(&self.data);
+        return Ok(bytes);
+    }
+
+    /// The size of the first dimension, the number of items in the file.
+    pub fn len(&self) -> usize {
+        self.dimensions[0]
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The number of values in one item, the product of all but the first dimension.
+    pub fn item_size(&self) -> usize {
+        self.dimensions[1..].iter().product()
+    }
+    /// The values as they are stored in the file.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+    /// Every value, in row-major order. Every type of value fits into an `f64` exactly.
+    pub fn values(&self) -> impl Iterator<Item = f64> + '_ {
+        self.data.chunks_exact(self.data_type.size()).map(move |value| self.data_type.decode(value))
+    }
+    /// The values of item `i`, each multiplied by `scale`.
+    pub fn item<T: Float>(&self, i: usize, scale: f64) -> Vec<T> {
+        let size = self.item_size() * self.data_type.size();
+        return self.data[i * size..(i + 1) * size].chunks_exact(self.data_type.size())
+            .map(|value| T::from_f64(self.data_type.decode(value) * scale))
+            .collect();
+    }
+    /// The items as the rows of a matrix, with every value multiplied by `scale`.
+    pub fn to_matrix<T: Float>(&self, scale: f64) -> Result<Matrix<T>, NnError> {
+        return Matrix::from_flat(self.len(), self.item_size(), self.values().map(|x| T::from_f64(x * scale)).collect());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TYPES: [IdxType; 6] = [IdxType::U8, IdxType::I8, IdxType::I16, IdxType::I32, IdxType::F32, IdxType::F64];
+
+    fn sample(data_type: IdxType) -> IdxArray {
+        let values = match data_type {
+            IdxType::U8 => vec![0.0, 1.0, 127.0, 128.0, 200.0, 255.0],
+            IdxType::F32 | IdxType::F64 => vec![-1.5, 0.0, 0.25, 3.0e10, -7.0, 1.0e-3],
+            _ => vec![-128.0, -1.0, 0.0, 1.0, 42.0, 127.0],
+        };
+        return IdxArray::new(data_type, vec![3, 2], values).unwrap();
+    }
+
+    #[test]
+    fn encode_and_decode_round_trip() {
+        for data_type in TYPES {
+            let array = sample(data_type);
+            let bytes = array.encode().unwrap();
+            assert_eq!(bytes[2], data_type.code());
+            assert_eq!(bytes.len(), 4 + 2 * 4 + 6 * data_type.size());
+            let decoded = IdxArray::decode(&bytes).unwrap();
+            assert_eq!(decoded, array);
+            assert_eq!(decoded.values().collect::<Vec<_>>(), array.values().collect::<Vec<_>>());
+        }
+        let array = sample(IdxType::U8);
+        assert_eq!(array.item::<f32>(1, 1.0), vec![127.0, 128.0]);
+        assert_eq!(array.to_matrix::<f64>(0.5).unwrap().row(2), &[100.0, 127.5]);
+    }
+
+    #[test]
+    fn rejects_truncated_and_malformed_files() {
+        let bytes = sample(IdxType::I16).encode().unwrap();
+        assert!(matches!(IdxArray::decode(&bytes[..bytes.len() - 1]), Err(NnError::InvalidData(_))));
+        assert!(matches!(IdxArray::decode(&bytes[..6]), Err(NnError::InvalidData(_))));
+        assert!(matches!(IdxArray::decode(&bytes[..3]), Err(NnError::InvalidData(_))));
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(matches!(IdxArray::decode(&extra), Err(NnError::InvalidData(_))));
+        let mut unknown = bytes.clone();
+        unknown[2] = 0x0A;
+        assert!(matches!(IdxArray::decode(&unknown), Err(NnError::InvalidData(_))));
+        assert!(matches!(IdxArray::new(IdxType::U8, vec![2, 2], vec![0.0; 3]), Err(NnError::LengthMismatch { .. })));
+    }
+}
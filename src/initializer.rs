@@ -0,0 +1,175 @@
+use std::f64::consts::PI;
+
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::error::NnError;
+use crate::float::Float;
+use crate::matrix::Matrix;
+
+/// How the parameters of a layer are filled before training.
+///
+/// The scaled strategies use the fan-in and fan-out of the layer, that is its
+/// number of inputs and outputs, so that the variance of the activations stays
+/// roughly the same from layer to layer.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Initializer {
+    /// Uniform values in [`low`, `high`).
+    Uniform { low: f64, high: f64 },
+    /// Glorot: uniform in ±sqrt(6 / (fan_in + fan_out)). Suits sigmoid and tanh.
+    XavierUniform,
+    /// Glorot: normal with a variance of 2 / (fan_in + fan_out).
+    XavierNormal,
+    /// Kaiming: uniform in ±sqrt(6 / fan_in). Suits ReLU and its relatives.
+    HeUniform,
+    /// Kaiming: normal with a variance of 2 / fan_in.
+    HeNormal,
+    /// Uniform in ±sqrt(3 / fan_in).
+    LecunUniform,
+    /// Normal with a variance of 1 / fan_in.
+    LecunNormal,
+    /// A matrix with orthonormal rows or columns, whichever are fewer, scaled by `gain`.
+    Orthogonal { gain: f64 },
+    Zeros,
+    Constant { value: f64 },
+}
+
+impl Default for Initializer {
+    /// Uniform in [-1, 1), what layers have always been initialized with.
+    fn default() -> Self {
+        Initializer::Uniform { low: -1.0, high: 1.0 }
+    }
+}
+
+/// A standard normal sample by the Box-Muller transform.
+fn standard_normal(rng: &mut dyn RngCore) -> f64 {
+    // 1 - u lies in (0, 1], so the logarithm stays finite.
+    let u: f64 = 1.0 - rng.gen::<f64>();
+    let v: f64 = rng.gen();
+    return (-2.0 * u.ln()).sqrt() * (2.0 * PI * v).cos();
+}
+
+/// Orthonormalizes the rows of a row-major `rows x cols` buffer with modified
+/// Gram-Schmidt. Needs `rows <= cols`.
+fn orthonormalize_rows(values: &mut [f64], rows: usize, cols: usize) {
+    for i in 0..rows {
+        for j in 0..i {
+            let dot: f64 = (0..cols).map(|k| values[i * cols + k] * values[j * cols + k]).sum();
+            for k in 0..cols {
+                values[i * cols + k] -= dot * values[j * cols + k];
+            }
+        }
+        let norm = (0..cols).map(|k| values[i * cols + k].powi(2)).sum::<f64>().sqrt();
+        for k in 0..cols {
+            values[i * cols + k] /= norm;
+        }
+    }
+}
+
+impl Initializer {
+    /// A `rows x cols` matrix for a layer with the given fan-in and fan-out.
+    pub fn initialize<T: Float>(&self, rows: usize, cols: usize, fan_in: usize, fan_out: usize, rng: &mut dyn RngCore) -> Result<Matrix<T>, NnError> {
+        if rows == 0 || cols == 0 {
+            return Err(NnError::EmptyDimension);
+        }
+        let fan_in = fan_in.max(1) as f64;
+        let fan_out = fan_out.max(1) as f64;
+        let count = rows * cols;
+        let uniform = |rng: &mut dyn RngCore, limit: f64| -> Vec<f64> {
+            (0..count).map(|_| (rng.gen::<f64>() * 2.0 - 1.0) * limit).collect()
+        };
+        let normal = |rng: &mut dyn RngCore, deviation: f64| -> Vec<f64> {
+            (0..count).map(|_| standard_normal(rng) * deviation).collect()
+        };
+        let values = match *self {
+            Initializer::Uniform { low, high } => (0..count).map(|_| low + rng.gen::<f64>() * (high - low)).collect(),
+            Initializer::XavierUniform => uniform(rng, (6.0 / (fan_in + fan_out)).sqrt()),
+            Initializer::XavierNormal => normal(rng, (2.0 / (fan_in + fan_out)).sqrt()),
+            Initializer::HeUniform => uniform(rng, (6.0 / fan_in).sqrt()),
+            Initializer::HeNormal => normal(rng, (2.0 / fan_in).sqrt()),
+            Initializer::LecunUniform => uniform(rng, (3.0 / fan_in).sqrt()),
+            Initializer::LecunNormal => normal(rng, (1.0 / fan_in).sqrt()),
+            Initializer::Orthogonal { gain } => {
+                // Orthonormalize along the longer side, transposing if there are more rows than columns.
+                let (short, long) = (rows.min(cols), rows.max(cols));
+                let mut values = normal(rng, 1.0);
+                orthonormalize_rows(&mut values, short, long);
+                if rows > cols {
+                    values = (0..count).map(|i| values[(i % cols) * rows + i / cols]).collect();
+                }
+                values.iter().map(|x| x * gain).collect()
+            }
+            Initializer::Zeros => vec![0.0; count],
+            Initializer::Constant { value } => vec![value; count],
+        };
+        return Matrix::from_flat(rows, cols, values.into_iter().map(T::from_f64).collect());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    fn sample(initializer: Initializer, rows: usize, cols: usize) -> Matrix<f64> {
+        return initializer.initialize(rows, cols, rows, cols, &mut ChaCha12Rng::seed_from_u64(11)).unwrap();
+    }
+
+    fn mean_and_variance(values: &[f64]) -> (f64, f64) {
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / values.len() as f64;
+        return (mean, variance);
+    }
+
+    #[test]
+    fn scaled_initializers_have_their_target_variance() {
+        let (fan_in, fan_out) = (200, 300);
+        let glorot = 2.0 / (fan_in + fan_out) as f64;
+        let kaiming = 2.0 / fan_in as f64;
+        let lecun = 1.0 / fan_in as f64;
+        let cases = [
+            (Initializer::XavierUniform, glorot),
+            (Initializer::XavierNormal, glorot),
+            (Initializer::HeUniform, kaiming),
+            (Initializer::HeNormal, kaiming),
+            (Initializer::LecunUniform, lecun),
+            (Initializer::LecunNormal, lecun),
+        ];
+        for &(initializer, target) in &cases {
+            let (mean, variance) = mean_and_variance(sample(initializer, fan_in, fan_out).values());
+            assert!(mean.abs() < 0.1 * target.sqrt(), "{:?} has a mean of {}", initializer, mean);
+            assert!((variance / target - 1.0).abs() < 0.03, "{:?} has a variance of {} instead of {}", initializer, variance, target);
+        }
+    }
+
+    #[test]
+    fn orthogonal_matrices_have_orthonormal_rows_or_columns() {
+        for &(rows, cols) in &[(4, 7), (7, 4), (5, 5)] {
+            let matrix = sample(Initializer::Orthogonal { gain: 2.0 }, rows, cols);
+            // The vectors along the shorter side are orthogonal with a length of `gain`.
+            let vectors = if rows <= cols { matrix } else { matrix.transpose() };
+            for a in 0..vectors.rows() {
+                for b in 0..vectors.rows() {
+                    let dot: f64 = vectors.row(a).iter().zip(vectors.row(b)).map(|(x, y)| x * y).sum();
+                    let expected = if a == b { 4.0 } else { 0.0 };
+                    assert!((dot - expected).abs() < 1e-9, "{}x{}: {} . {} = {}", rows, cols, a, b, dot);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_and_uniform_initializers_stay_in_bounds() {
+        assert!(sample(Initializer::Zeros, 3, 4).values().iter().all(|&x| x == 0.0));
+        assert!(sample(Initializer::Constant { value: 0.1 }, 3, 4).values().iter().all(|&x| x == 0.1));
+        let uniform = sample(Initializer::Uniform { low: -0.5, high: 2.0 }, 50, 40);
+        assert!(uniform.values().iter().all(|&x| (-0.5..2.0).contains(&x)));
+        let (mean, _) = mean_and_variance(uniform.values());
+        assert!((mean - 0.75).abs() < 0.05);
+        let limit = (6.0 / 90.0f64).sqrt();
+        assert!(sample(Initializer::XavierUniform, 50, 40).values().iter().all(|&x| x.abs() <= limit));
+        assert!(matches!(Initializer::Zeros.initialize::<f64>(0, 3, 1, 1, &mut ChaCha12Rng::seed_from_u64(0)), Err(NnError::EmptyDimension)));
+    }
+}
@@ -1,31 +1,59 @@
 use std::fmt::{Debug, Formatter};
 
+use crate::activation::Activation;
+use crate::error::NnError;
+use crate::float::Float;
+use crate::initializer::Initializer;
 use crate::matrix::Matrix;
-use serde::Serialize;
-use serde::Deserialize;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, serde::Serialize, Deserialize)]
-pub struct Layer {
-    pub weights: Matrix,
-    pub biases: Matrix,
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct Layer<T: Float = f32> {
+    pub weights: Matrix<T>,
+    pub biases: Matrix<T>,
+    /// Networks saved before activations were stored on the layers used sigmoid.
+    #[serde(default)]
+    pub activation: Activation,
 }
 
-impl Layer {
-    pub fn new(input_count: usize, output_count: usize) -> Result<Layer, String> {
-        let weights = Matrix::new_random(input_count, output_count)?;
-        let biases = Matrix::new_random(1, output_count)?;
-        return Ok(Layer { weights, biases });
-    }
-    pub fn get_result(&self, input: &Matrix, activation_function: &dyn Fn(f32) -> f32) -> Result<Matrix, String> {
-        let mut result = Matrix::matrix_multiplication(input, &self.weights)?;
-        result = Matrix::matrix_addition_filling_rows(&result, &self.biases)?;
-        result = result.apply_function(activation_function);
-        return Ok(result);
+impl<T: Float> Layer<T> {
+    pub fn new(input_count: usize, output_count: usize, activation: Activation) -> Result<Layer<T>, NnError> {
+        return Layer::new_with_rng(input_count, output_count, activation, &mut rand::thread_rng());
+    }
+    /// Like `new`, but initializes the parameters from the given generator.
+    pub fn new_with_rng(input_count: usize, output_count: usize, activation: Activation, rng: &mut dyn RngCore) -> Result<Layer<T>, NnError> {
+        return Layer::initialized(input_count, output_count, activation, Initializer::default(), Initializer::default(), rng);
+    }
+    /// Builds a layer whose weights and biases are filled by their own initializers.
+    pub fn initialized(input_count: usize, output_count: usize, activation: Activation, weights: Initializer, biases: Initializer, rng: &mut dyn RngCore) -> Result<Layer<T>, NnError> {
+        let weights = weights.initialize(input_count, output_count, input_count, output_count, rng)?;
+        let biases = biases.initialize(1, output_count, input_count, output_count, rng)?;
+        return Ok(Layer { weights, biases, activation });
+    }
+    pub fn input_count(&self) -> usize {
+        self.weights.rows()
+    }
+    pub fn output_count(&self) -> usize {
+        self.weights.cols()
+    }
+    /// The weighted sum of the inputs plus the biases, before the activation is applied.
+    pub fn get_pre_activation(&self, input: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        let result = Matrix::matrix_multiplication(input, &self.weights)?;
+        return Matrix::matrix_addition_filling_rows(&result, &self.biases);
+    }
+    pub fn get_result(&self, input: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        return Ok(self.activation.forward(&self.get_pre_activation(input)?));
+    }
+    /// Converts the layer to another precision.
+    pub fn cast<U: Float>(&self) -> Layer<U> {
+        return Layer { weights: self.weights.cast(), biases: self.biases.cast(), activation: self.activation };
     }
 }
 
-impl Debug for Layer {
+impl<T: Float> Debug for Layer<T> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Weights:\n{:?}\n\nBiases:\n{:?}\n\n", self.weights, self.biases)
+        write!(f, "Activation: {:?}\n\nWeights:\n{:?}\n\nBiases:\n{:?}\n\n", self.activation, self.weights, self.biases)
     }
 }
\ No newline at end of file
@@ -0,0 +1,49 @@
+#![allow(clippy::needless_return)]
+
+//! A small neural network library trained with backpropagation.
+//!
+//! `Matrix` holds the values, `Layer` and `Network` build the model on top of
+//! it and `mnist_parser` loads the MNIST data set.
+
+pub mod activation;
+pub mod binary;
+pub mod callback;
+pub mod checkpoint;
+pub mod csv;
+pub mod dataset;
+pub mod error;
+pub mod float;
+mod gemm;
+pub mod idx;
+pub mod initializer;
+pub mod layer;
+pub mod loss;
+pub mod matrix;
+pub mod metrics;
+pub mod mnist_parser;
+pub mod model;
+pub mod network;
+pub mod optimizer;
+pub mod schedule;
+pub mod trainer;
+mod ops;
+
+pub use crate::activation::Activation;
+pub use crate::callback::Callback;
+pub use crate::csv::{CsvDataset, CsvLoader, Preprocessor};
+pub use crate::dataset::{DataLoader, Dataset, InMemoryDataset};
+pub use crate::error::NnError;
+pub use crate::float::{Float, Precision};
+pub use crate::initializer::Initializer;
+pub use crate::layer::Layer;
+pub use crate::loss::Loss;
+pub use crate::matrix::Matrix;
+pub use crate::metrics::{ConfusionMatrix, Labels};
+pub use crate::mnist_parser::MnistDataset;
+pub use crate::model::ModelInfo;
+pub use crate::network::{ForwardPass, LayerGradients, Network};
+pub use crate::optimizer::Optimizer;
+pub use crate::schedule::LrSchedule;
+pub use crate::trainer::{History, Trainer};
+/// The seedable random number generator used for initialization and shuffling.
+pub use rand_chacha::ChaCha12Rng;
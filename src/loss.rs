@@ -0,0 +1,235 @@
+use crate::activation::Activation;
+use crate::error::NnError;
+use crate::float::Float;
+use crate::matrix::Matrix;
+
+/// A loss function that training minimizes.
+///
+/// Values are averaged over the rows of the batch, and gradients are taken of
+/// that average with respect to the outputs of the network.
+pub trait Loss<T: Float> {
+    fn name(&self) -> &'static str;
+    fn value(&self, expected: &Matrix<T>, actual: &Matrix<T>) -> Result<T, NnError>;
+    fn gradient(&self, expected: &Matrix<T>, actual: &Matrix<T>) -> Result<Matrix<T>, NnError>;
+    /// The gradient with respect to the pre-activation of the output layer.
+    ///
+    /// Losses that pair with an output activation, like the cross-entropies, can
+    /// override this with a simpler and numerically stabler combined gradient.
+    fn output_delta(&self, activation: Activation, pre_activation: &Matrix<T>, expected: &Matrix<T>, actual: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        return activation.backward(pre_activation, actual, &self.gradient(expected, actual)?);
+    }
+}
+
+/// The squared differences summed over the outputs of a sample.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MeanSquaredError;
+
+/// The absolute differences summed over the outputs of a sample.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MeanAbsoluteError;
+
+/// Quadratic for differences up to `delta` and linear beyond, which makes it
+/// less sensitive to outliers than the squared error.
+#[derive(Clone, Copy, Debug)]
+pub struct Huber {
+    pub delta: f64,
+}
+
+impl Default for Huber {
+    fn default() -> Self {
+        Huber { delta: 1.0 }
+    }
+}
+
+/// The cross-entropy of one-hot targets against softmax outputs.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CategoricalCrossEntropy;
+
+/// The cross-entropy of targets in [0, 1] against sigmoid outputs, averaged
+/// over every output instead of every sample.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BinaryCrossEntropy;
+
+/// `max(0, 1 - y * a)` for targets of -1 or 1. Targets of 0 count as -1, so
+/// one-hot labels can be used directly.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Hinge;
+
+/// Keeps probabilities away from 0 and 1 so that their logarithm stays finite.
+fn clamp_probability<T: Float>(p: T) -> T {
+    let epsilon = T::from_f64(1e-7);
+    p.max(epsilon).min(T::one() - epsilon)
+}
+
+/// Sums `f(expected, actual)` over every pair of values and divides by `count`.
+fn mean_of<T: Float>(op: &'static str, expected: &Matrix<T>, actual: &Matrix<T>, count: usize, f: &dyn Fn(T, T) -> T) -> Result<T, NnError> {
+    Matrix::check_same_shape(op, expected, actual)?;
+    let sum: T = expected.values().iter().zip(actual.values()).map(|(&y, &a)| f(y, a)).sum();
+    return Ok(sum / T::from_usize(count));
+}
+
+/// Builds the matrix of `f(expected, actual) / count` for every pair of values.
+fn gradient_of<T: Float>(op: &'static str, expected: &Matrix<T>, actual: &Matrix<T>, count: usize, f: &dyn Fn(T, T) -> T) -> Result<Matrix<T>, NnError> {
+    Matrix::check_same_shape(op, expected, actual)?;
+    let scale = T::one() / T::from_usize(count);
+    let values = expected.values().iter().zip(actual.values()).map(|(&y, &a)| f(y, a) * scale).collect();
+    return Matrix::from_flat(actual.rows(), actual.cols(), values);
+}
+
+impl<T: Float> Loss<T> for MeanSquaredError {
+    fn name(&self) -> &'static str {
+        "mean_squared_error"
+    }
+    fn value(&self, expected: &Matrix<T>, actual: &Matrix<T>) -> Result<T, NnError> {
+        return mean_of("compute the squared error of", expected, actual, expected.rows(), &|y, a| (a - y) * (a - y));
+    }
+    fn gradient(&self, expected: &Matrix<T>, actual: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        let two = T::from_f64(2.0);
+        return gradient_of("compute the squared error of", expected, actual, expected.rows(), &|y, a| two * (a - y));
+    }
+}
+
+impl<T: Float> Loss<T> for MeanAbsoluteError {
+    fn name(&self) -> &'static str {
+        "mean_absolute_error"
+    }
+    fn value(&self, expected: &Matrix<T>, actual: &Matrix<T>) -> Result<T, NnError> {
+        return mean_of("compute the absolute error of", expected, actual, expected.rows(), &|y, a| (a - y).abs());
+    }
+    fn gradient(&self, expected: &Matrix<T>, actual: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        return gradient_of("compute the absolute error of", expected, actual, expected.rows(), &|y, a| {
+            if a > y { T::one() } else if a < y { -T::one() } else { T::zero() }
+        });
+    }
+}
+
+impl<T: Float> Loss<T> for Huber {
+    fn name(&self) -> &'static str {
+        "huber"
+    }
+    fn value(&self, expected: &Matrix<T>, actual: &Matrix<T>) -> Result<T, NnError> {
+        let delta = T::from_f64(self.delta);
+        let half = T::from_f64(0.5);
+        return mean_of("compute the huber loss of", expected, actual, expected.rows(), &|y, a| {
+            let difference = (a - y).abs();
+            if difference <= delta { half * difference * difference } else { delta * (difference - half * delta) }
+        });
+    }
+    fn gradient(&self, expected: &Matrix<T>, actual: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        let delta = T::from_f64(self.delta);
+        return gradient_of("compute the huber loss of", expected, actual, expected.rows(), &|y, a| (a - y).max(-delta).min(delta));
+    }
+}
+
+impl<T: Float> Loss<T> for CategoricalCrossEntropy {
+    fn name(&self) -> &'static str {
+        "categorical_cross_entropy"
+    }
+    fn value(&self, expected: &Matrix<T>, actual: &Matrix<T>) -> Result<T, NnError> {
+        return mean_of("compute the cross-entropy of", expected, actual, expected.rows(), &|y, p| -y * clamp_probability(p).ln());
+    }
+    fn gradient(&self, expected: &Matrix<T>, actual: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        return gradient_of("compute the cross-entropy of", expected, actual, expected.rows(), &|y, p| -y / clamp_probability(p));
+    }
+    /// Through a softmax the gradient collapses to `(p - y) / rows`.
+    fn output_delta(&self, activation: Activation, pre_activation: &Matrix<T>, expected: &Matrix<T>, actual: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        if let Activation::Softmax = activation {
+            return gradient_of("compute the cross-entropy of", expected, actual, expected.rows(), &|y, p| p - y);
+        }
+        return activation.backward(pre_activation, actual, &self.gradient(expected, actual)?);
+    }
+}
+
+impl<T: Float> Loss<T> for BinaryCrossEntropy {
+    fn name(&self) -> &'static str {
+        "binary_cross_entropy"
+    }
+    fn value(&self, expected: &Matrix<T>, actual: &Matrix<T>) -> Result<T, NnError> {
+        return mean_of("compute the cross-entropy of", expected, actual, expected.values().len(), &|y, p| {
+            let p = clamp_probability(p);
+            -(y * p.ln() + (T::one() - y) * (T::one() - p).ln())
+        });
+    }
+    fn gradient(&self, expected: &Matrix<T>, actual: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        return gradient_of("compute the cross-entropy of", expected, actual, expected.values().len(), &|y, p| {
+            let p = clamp_probability(p);
+            (p - y) / (p * (T::one() - p))
+        });
+    }
+    /// Through a sigmoid the gradient collapses to `(p - y) / n`.
+    fn output_delta(&self, activation: Activation, pre_activation: &Matrix<T>, expected: &Matrix<T>, actual: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        if let Activation::Sigmoid = activation {
+            return gradient_of("compute the cross-entropy of", expected, actual, expected.values().len(), &|y, p| p - y);
+        }
+        return activation.backward(pre_activation, actual, &self.gradient(expected, actual)?);
+    }
+}
+
+/// Maps 0/1 targets onto -1/1.
+fn hinge_target<T: Float>(y: T) -> T {
+    if y > T::zero() { y } else { -T::one() }
+}
+
+impl<T: Float> Loss<T> for Hinge {
+    fn name(&self) -> &'static str {
+        "hinge"
+    }
+    fn value(&self, expected: &Matrix<T>, actual: &Matrix<T>) -> Result<T, NnError> {
+        return mean_of("compute the hinge loss of", expected, actual, expected.rows(), &|y, a| {
+            (T::one() - hinge_target(y) * a).max(T::zero())
+        });
+    }
+    fn gradient(&self, expected: &Matrix<T>, actual: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        return gradient_of("compute the hinge loss of", expected, actual, expected.rows(), &|y, a| {
+            let y = hinge_target(y);
+            if y * a < T::one() { -y } else { T::zero() }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compares `output_delta` with central differences of the loss with
+    /// respect to every pre-activation.
+    fn check_gradient(loss: &dyn Loss<f64>, activation: Activation, expected: Matrix<f64>) {
+        let pre_activation = Matrix::from_flat(2, 3, vec![0.3, -1.2, 0.8, -0.4, 1.5, 0.1]).unwrap();
+        let actual = activation.forward(&pre_activation);
+        let delta = loss.output_delta(activation, &pre_activation, &expected, &actual).unwrap();
+        let h = 1e-6;
+        for i in 0..pre_activation.values().len() {
+            let mut plus = pre_activation.clone();
+            plus.values_mut()[i] += h;
+            let mut minus = pre_activation.clone();
+            minus.values_mut()[i] -= h;
+            let numeric = (loss.value(&expected, &activation.forward(&plus)).unwrap() - loss.value(&expected, &activation.forward(&minus)).unwrap()) / (2.0 * h);
+            assert!((numeric - delta.values()[i]).abs() < 1e-7, "{} through {:?} at {}: {} vs {}", loss.name(), activation, i, numeric, delta.values()[i]);
+        }
+    }
+
+    fn one_hot() -> Matrix<f64> {
+        Matrix::from_flat(2, 3, vec![0.0, 1.0, 0.0, 1.0, 0.0, 0.0]).unwrap()
+    }
+    fn targets() -> Matrix<f64> {
+        Matrix::from_flat(2, 3, vec![0.5, -2.0, 0.2, 0.9, 1.0, 2.0]).unwrap()
+    }
+
+    #[test]
+    fn cross_entropies_match_finite_differences() {
+        check_gradient(&CategoricalCrossEntropy, Activation::Softmax, one_hot());
+        check_gradient(&CategoricalCrossEntropy, Activation::Sigmoid, one_hot());
+        check_gradient(&BinaryCrossEntropy, Activation::Sigmoid, one_hot());
+        check_gradient(&BinaryCrossEntropy, Activation::Softmax, one_hot());
+    }
+
+    #[test]
+    fn regression_losses_match_finite_differences() {
+        check_gradient(&MeanSquaredError, Activation::Linear, targets());
+        check_gradient(&MeanSquaredError, Activation::Tanh, targets());
+        check_gradient(&MeanAbsoluteError, Activation::Linear, targets());
+        check_gradient(&Huber::default(), Activation::Linear, targets());
+        check_gradient(&Huber { delta: 0.5 }, Activation::Sigmoid, targets());
+        check_gradient(&Hinge, Activation::Linear, one_hot());
+    }
+}
@@ -1,64 +1,115 @@
+use std::env;
 use std::fs::File;
 use std::io::Write;
-use std::time::{Instant, SystemTime};
 
-use chrono::{Local, Utc};
-use rand::seq::IteratorRandom;
+use chrono::Local;
+use rand::SeedableRng;
+use neuralnetwork::mnist_parser::{load_images, load_labels, MnistDataset};
+use neuralnetwork::binary::{binary_to_json, json_to_binary};
+use neuralnetwork::layer::Layer;
+use neuralnetwork::loss::CategoricalCrossEntropy;
+use neuralnetwork::optimizer::Adam;
+use neuralnetwork::callback::{EarlyStopping, ProgressLogger, ScheduleUnit};
+use neuralnetwork::checkpoint::{self, CheckpointPolicy};
+use neuralnetwork::metrics::evaluate;
+use neuralnetwork::trainer::Metric;
+use neuralnetwork::{Activation, ChaCha12Rng, DataLoader, Dataset, Initializer, LrSchedule, ModelInfo, Network, Trainer};
 
-use crate::matrix::Matrix;
-use crate::mnist_parser::{get_input_vec, get_labels};
-use crate::network::Network;
-use crate::utils::cost;
+/// Seeds weight initialization and shuffling, so that every run trains the same network.
+const SEED: u64 = 42;
 
-mod matrix;
-mod layer;
-mod network;
-mod utils;
-mod mnist_parser;
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("evaluate") => evaluate_test_set(&args[2..]),
+        Some("convert") => convert(&args[2..]),
+        Some("resume") => train(true),
+        _ => train(false),
+    }
+}
+
+/// `evaluate <network.json> [misclassified.txt]` reports how a saved network
+/// does on the MNIST test set, and optionally writes the indices of the
+/// misclassified images to a file, one per line.
+fn evaluate_test_set(args: &[String]) {
+    let network_path = args.first().expect("usage: evaluate <network.json> [misclassified.txt]");
+    let network: Network = Network::load(network_path).unwrap();
 
-fn sigmoid(input: f32) -> f32 {
-    input.exp() / (input.exp() + 1.0)
+    let inputs = load_images("data/t10k-images.idx3-ubyte").unwrap();
+    let labels = load_labels("data/t10k-labels.idx1-ubyte").unwrap();
+
+    let evaluation = evaluate(&network, &inputs, &labels, &CategoricalCrossEntropy, 100).unwrap();
+    println!("Loss: {:.4}, accuracy: {:.4} ({} of {} misclassified)\n",
+             evaluation.loss, evaluation.accuracy, evaluation.misclassified.len(), inputs.rows());
+    println!("{}", evaluation.confusion);
+    println!("{}", evaluation.confusion.report());
+
+    if let Some(misclassified_path) = args.get(1) {
+        let mut file = File::create(misclassified_path).unwrap();
+        for index in &evaluation.misclassified {
+            writeln!(file, "{}", index).unwrap();
+        }
+    }
 }
 
-fn d_sigmoid(input: f32) -> f32 {
-    input * (1.0 - input)
+/// `convert <from> <to>` converts a saved network between the JSON and the
+/// binary format. The target is written as JSON if its name ends in `.json`.
+fn convert(args: &[String]) {
+    let (from, to) = match args {
+        [from, to] => (from, to),
+        _ => panic!("usage: convert <from> <to>"),
+    };
+    if to.ends_with(".json") {
+        binary_to_json(from, to).unwrap();
+    } else {
+        json_to_binary(from, to).unwrap();
+    }
 }
 
-fn main() {
-    let mut network = Network::new(&[784, 100, 100, 10]).unwrap();
+/// Trains a new network, or with `resume` continues from the newest checkpoint
+/// in `data/checkpoints`.
+fn train(resume: bool) {
+    let mut rng = ChaCha12Rng::seed_from_u64(SEED);
+    let mut network: Network = Network::from_layers(vec![
+        Layer::initialized(784, 100, Activation::Sigmoid, Initializer::XavierUniform, Initializer::Zeros, &mut rng).unwrap(),
+        Layer::initialized(100, 100, Activation::Sigmoid, Initializer::XavierUniform, Initializer::Zeros, &mut rng).unwrap(),
+        Layer::initialized(100, 10, Activation::Softmax, Initializer::XavierUniform, Initializer::Zeros, &mut rng).unwrap(),
+    ]).unwrap();
     println!("{:?}", network);
 
-    let inputs = get_input_vec("data/train-images.idx3-ubyte".parse().unwrap(), 100).unwrap();
-
-    let expected_results = get_labels("data/train-labels.idx1-ubyte".parse().unwrap(), 100).unwrap();
+    let mut train_set: MnistDataset = MnistDataset::load("data/train-images.idx3-ubyte", "data/train-labels.idx1-ubyte").unwrap();
     println!("Finished loading the values.");
 
+    let validation_set = train_set.split_off(train_set.len() - 10000).unwrap();
+    let train_loader = DataLoader::new(train_set).with_batch_size(100).with_workers(2);
+    let validation_loader = DataLoader::new(validation_set).with_batch_size(1000).with_shuffle(false);
 
-    // let mut inputs = vec![];
-    // let mut expected_results = vec![];
-    // for i in 0..100{
-    //     let mut temp_in: Vec<Vec<f32>> = vec![];
-    //
-    //     let mut temp_out: Vec<Vec<f32>> = vec![];
-    //     for _j in 0..8{
-    //         let x: f32 = rand::random::<f32>() * std::f32::consts::PI * 2.0;
-    //         temp_in.push(vec![x]);
-    //         temp_out.push((vec![(x.sin() + 1.0) / 2.0]));
-    //     }
-    //     inputs.push(Matrix::from_values(temp_in).unwrap());
-    //     expected_results.push(Matrix::from_values(temp_out).unwrap());
-    // }
-
-    let epochs = 5000;
-    for i in 0..epochs {
-        let index = (0..inputs.len()).choose(&mut rand::thread_rng()).unwrap();
-        let result = network.feedforward(inputs[index].clone(), &sigmoid).unwrap();
-
-        network.backpropagate(&result, expected_results[index].clone(), &d_sigmoid, 0.001);
-        if i % 500 == 0 || i == epochs - 1 {
-            println!("Result: {:?}, \nDesired: {:?}, \nCost: {:?}\n", result.last().unwrap().clone(), expected_results[index].clone(), cost(&result.last().unwrap(), &expected_results[index]));
+    let mut trainer = Trainer::new(Box::new(CategoricalCrossEntropy), Adam::new())
+        .with_epochs(30)
+        .with_rng(rng)
+        .with_schedule(LrSchedule::linear_warmup(100, LrSchedule::step_decay(0.001, 0.5, 2000)), ScheduleUnit::Step)
+        .with_early_stopping(EarlyStopping::new(Metric::ValidationLoss, 3, 1e-4))
+        .with_callback(ProgressLogger::every_batches(100))
+        .with_checkpoints(CheckpointPolicy::new("data/checkpoints", 1, 3));
+    if resume {
+        let path = checkpoint::latest("data/checkpoints").unwrap().expect("there is no checkpoint to resume from");
+        trainer.resume(&mut network, &path).unwrap();
+        println!("Resuming from {}", path.display());
+    }
+    let history = trainer.fit_loader(&mut network, &train_loader, Some(&validation_loader)).unwrap();
+    if let Some(best_epoch) = history.best_epoch {
+        println!("Restored the weights of epoch {}", best_epoch);
+    }
+    // The loader, not the trainer, decided the batch size.
+    let mut config = trainer.config();
+    config.batch_size = train_loader.batch_size;
+    let mut info = ModelInfo { training: Some(config), ..ModelInfo::default() };
+    info.metadata.dataset = Some("mnist".to_string());
+    if let Some(last) = history.best_epoch.map_or(history.epochs.last(), |epoch| history.epochs.get(epoch)) {
+        if let (Some(loss), Some(accuracy)) = (last.validation_loss, last.validation_accuracy) {
+            info.metadata.metrics.insert("validation_loss".to_string(), loss);
+            info.metadata.metrics.insert("validation_accuracy".to_string(), accuracy);
         }
     }
-    let mut networkfile = File::create(format!("data/nn-{:?}.json", Local::now())).unwrap();
-    write!(networkfile, "{}", serde_json::to_string(&network).unwrap()).unwrap();
-}
\ No newline at end of file
+    network.save_with_info(format!("data/nn-{:?}.json", Local::now()), &info).unwrap();
+}
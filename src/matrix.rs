@@ -1,268 +1,415 @@
+use std::convert::TryFrom;
 use std::fmt::{Debug, Formatter};
+
+use rand::{Rng, RngCore};
 use serde::{Deserialize, Serialize};
 
+use crate::error::NnError;
+use crate::float::Float;
+use crate::gemm::{self, View};
 
-#[derive(Serialize, Deserialize)]
-pub struct Matrix {
-    pub(crate) values: Vec<Vec<f32>>,
+/// A dense matrix stored row-major in a single contiguous buffer, generic over
+/// its element type so the same code runs in `f32` or `f64`.
+///
+/// The element at `(row, col)` lives at `row * cols + col`.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "MatrixRepr<T>", bound = "T: Float")]
+pub struct Matrix<T: Float = f32> {
+    pub(crate) values: Vec<T>,
     pub(crate) rows: usize,
     pub(crate) cols: usize,
 }
 
-impl Matrix {}
+/// The on-disk values of a matrix. Networks written before the switch to flat
+/// storage serialized one array per row, so both layouts are accepted.
+#[derive(Deserialize)]
+#[serde(untagged, bound = "T: Float")]
+enum MatrixValues<T> {
+    Flat(Vec<T>),
+    Nested(Vec<Vec<T>>),
+}
+
+#[derive(Deserialize)]
+#[serde(bound = "T: Float")]
+struct MatrixRepr<T> {
+    values: MatrixValues<T>,
+    rows: usize,
+    cols: usize,
+}
 
-impl Matrix {
-    pub fn new_zeroed(rows: usize, cols: usize) -> Result<Matrix, String> {
+impl<T: Float> TryFrom<MatrixRepr<T>> for Matrix<T> {
+    type Error = NnError;
+
+    fn try_from(repr: MatrixRepr<T>) -> Result<Self, Self::Error> {
+        let MatrixRepr { values, rows, cols } = repr;
         if rows == 0 || cols == 0 {
-            return Err("The dimensions may not be 0.".parse().unwrap());
+            return Err(NnError::EmptyDimension);
         }
-        let mut values = Vec::with_capacity(rows);
-        for i in 0..rows {
-            values.push(Vec::with_capacity(cols));
-            for _ in 0..cols {
-                values[i].push(0.0);
+        let values = match values {
+            MatrixValues::Flat(values) => values,
+            MatrixValues::Nested(nested) => {
+                if nested.iter().any(|row| row.len() != cols) {
+                    return Err(NnError::RaggedRows);
+                }
+                nested.concat()
             }
+        };
+        if values.len() != rows * cols {
+            return Err(NnError::LengthMismatch { expected: rows * cols, found: values.len() });
         }
         return Ok(Matrix { values, rows, cols });
     }
-    pub fn new_random(rows: usize, cols: usize) -> Result<Matrix, String> {
+}
+
+impl<T: Float> Matrix<T> {
+    pub fn new_zeroed(rows: usize, cols: usize) -> Result<Matrix<T>, NnError> {
         if rows == 0 || cols == 0 {
-            return Err("The dimensions may not be 0.".parse().unwrap());
+            return Err(NnError::EmptyDimension);
         }
-        let mut values = Vec::with_capacity(rows);
-        for i in 0..rows {
-            values.push(Vec::with_capacity(cols));
-            for _ in 0..cols {
-                values[i].push(rand::random::<f32>() * 2.0_f32 - 1.0);
-            }
+        return Ok(Matrix { values: vec![T::zero(); rows * cols], rows, cols });
+    }
+    /// Fills a matrix with values drawn uniformly from [-1, 1) by the thread's generator.
+    pub fn new_random(rows: usize, cols: usize) -> Result<Matrix<T>, NnError> {
+        return Matrix::new_random_with(rows, cols, &mut rand::thread_rng());
+    }
+    /// Like `new_random`, but draws from the given generator, so that a seeded
+    /// generator gives the same matrix every time.
+    pub fn new_random_with(rows: usize, cols: usize, rng: &mut dyn RngCore) -> Result<Matrix<T>, NnError> {
+        if rows == 0 || cols == 0 {
+            return Err(NnError::EmptyDimension);
         }
+        let values = (0..rows * cols).map(|_| T::from_f64(rng.gen::<f64>() * 2.0 - 1.0)).collect();
         return Ok(Matrix { values, rows, cols });
     }
-    pub fn from_values(values: Vec<Vec<f32>>) -> Result<Matrix, String> {
-        if values.len() == 0 || values[0].len() == 0 {
-            return Err("The dimensions may not be 0.".parse().unwrap());
+    pub fn from_values(values: Vec<Vec<T>>) -> Result<Matrix<T>, NnError> {
+        if values.is_empty() || values[0].is_empty() {
+            return Err(NnError::EmptyDimension);
         }
         let rows = values.len();
         let cols = values[0].len();
+        if values.iter().any(|row| row.len() != cols) {
+            return Err(NnError::RaggedRows);
+        }
 
+        return Ok(Matrix { values: values.concat(), rows, cols });
+    }
+    /// Builds a matrix from values that are already laid out row-major.
+    pub fn from_flat(rows: usize, cols: usize, values: Vec<T>) -> Result<Matrix<T>, NnError> {
+        if rows == 0 || cols == 0 {
+            return Err(NnError::EmptyDimension);
+        }
+        if values.len() != rows * cols {
+            return Err(NnError::LengthMismatch { expected: rows * cols, found: values.len() });
+        }
         return Ok(Matrix { values, rows, cols });
     }
 
-    pub(crate) fn matrix_addition_filling_cols(matrix: &Matrix, matrix2: &Matrix) -> Result<Matrix, String> {
-        if matrix.rows != matrix2.rows {
-            return Err("The dimensions of the two matrices do not match.".parse().unwrap());
+    #[inline]
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+    #[inline]
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+    /// All values in row-major order.
+    #[inline]
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+    #[inline]
+    pub fn values_mut(&mut self) -> &mut [T] {
+        &mut self.values
+    }
+    pub fn into_values(self) -> Vec<T> {
+        self.values
+    }
+    /// The dimensions as `(rows, cols)`.
+    #[inline]
+    pub fn shape(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+    /// Fails with a `ShapeMismatch` for `op` unless both matrices have the same dimensions.
+    pub(crate) fn check_same_shape(op: &'static str, matrix: &Matrix<T>, matrix2: &Matrix<T>) -> Result<(), NnError> {
+        if matrix.shape() != matrix2.shape() {
+            return Err(NnError::ShapeMismatch { op, left: matrix.shape(), right: matrix2.shape() });
+        }
+        return Ok(());
+    }
+
+    /// The position of `(row, col)` in the flat value buffer.
+    #[inline]
+    pub(crate) fn offset(&self, row: usize, col: usize) -> usize {
+        row * self.cols + col
+    }
+    #[inline]
+    pub fn get(&self, row: usize, col: usize) -> T {
+        self.values[self.offset(row, col)]
+    }
+    #[inline]
+    pub fn set(&mut self, row: usize, col: usize, value: T) {
+        let offset = self.offset(row, col);
+        self.values[offset] = value;
+    }
+    /// The values of a single row as a slice into the buffer.
+    #[inline]
+    pub fn row(&self, i: usize) -> &[T] {
+        &self.values[i * self.cols..(i + 1) * self.cols]
+    }
+    #[inline]
+    pub fn row_mut(&mut self, i: usize) -> &mut [T] {
+        let cols = self.cols;
+        &mut self.values[i * cols..(i + 1) * cols]
+    }
+
+    pub fn matrix_addition_filling_cols(matrix: &Matrix<T>, matrix2: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        if matrix.rows != matrix2.rows || matrix2.cols != 1 {
+            return Err(NnError::ShapeMismatch { op: "add a column to", left: matrix.shape(), right: matrix2.shape() });
         }
-        let mut values = Vec::with_capacity(matrix.rows);
+        let mut result = matrix.clone();
         for i in 0..matrix.rows {
-            values.push(Vec::with_capacity(matrix.cols));
-            for j in 0..matrix.cols {
-                values[i].push(matrix.values[i][j] + matrix2.values[i][0]);
+            let value = matrix2.get(i, 0);
+            for x in result.row_mut(i) {
+                *x += value;
             }
         }
-        return Ok(Matrix {
-            values,
-            rows: matrix.rows,
-            cols: matrix.cols,
-        });
+        return Ok(result);
     }
-    pub(crate) fn matrix_addition_filling_rows(matrix: &Matrix, matrix2: &Matrix) -> Result<Matrix, String> {
-        if matrix.cols != matrix2.cols {
-            return Err("The dimensions of the two matrices do not match.".parse().unwrap());
+    pub fn matrix_addition_filling_rows(matrix: &Matrix<T>, matrix2: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        if matrix.cols != matrix2.cols || matrix2.rows != 1 {
+            return Err(NnError::ShapeMismatch { op: "add a row to", left: matrix.shape(), right: matrix2.shape() });
         }
-        let mut values = Vec::with_capacity(matrix.rows);
+        let mut result = matrix.clone();
+        let bias = matrix2.row(0);
         for i in 0..matrix.rows {
-            values.push(Vec::with_capacity(matrix.cols));
-            for j in 0..matrix.cols {
-                values[i].push(matrix.values[i][j] + matrix2.values[0][j]);
+            for (x, b) in result.row_mut(i).iter_mut().zip(bias) {
+                *x += *b;
             }
         }
-        return Ok(Matrix {
-            values,
-            rows: matrix.rows,
-            cols: matrix.cols,
-        });
-    }
-    pub fn get_single_col(&self, i: usize) -> Matrix {
-        let mut values = Vec::with_capacity(self.rows);
-        for row in 0..self.rows {
-            values.push(Vec::with_capacity(1));
-            values[row].push(self.values[row][i]);
-        }
-        return Matrix { values, rows: self.rows, cols: 1 };
+        return Ok(result);
     }
-    pub fn get_single_row(&self, i: usize) -> Matrix {
-        let mut values = Vec::with_capacity(1);
-        values.push(Vec::with_capacity(self.cols));
-        for j in 0..self.cols {
-            values[0].push(self.values[i][j]);
+    /// Builds a matrix out of the given rows, in the given order.
+    pub fn select_rows(&self, indices: &[usize]) -> Result<Matrix<T>, NnError> {
+        if indices.is_empty() {
+            return Err(NnError::EmptyDimension);
         }
-        return Matrix { values, rows: 1, cols: self.cols };
-    }
-
-    pub fn scalar_multiplication(matrix: &Matrix, scalar: f32) -> Matrix {
-        let mut values = Vec::with_capacity(matrix.rows);
-        for i in 0..matrix.rows {
-            values.push(Vec::with_capacity(matrix.cols));
-            for j in 0..matrix.cols {
-                values[i].push(matrix.values[i][j] * scalar);
+        let mut values = Vec::with_capacity(indices.len() * self.cols);
+        for &i in indices {
+            if i >= self.rows {
+                return Err(NnError::RowOutOfRange { row: i, rows: self.rows });
             }
+            values.extend_from_slice(self.row(i));
         }
-        return Matrix { values, rows: matrix.rows, cols: matrix.cols };
+        return Ok(Matrix { values, rows: indices.len(), cols: self.cols });
     }
-    pub fn scalar_multiplication_mut(&mut self, scalar: f32){
-        for i in 0..self.rows {
-            for j in 0..self.cols {
-                self.values[i][j] *=  scalar;
-            }
+    /// Splits the rows into consecutive batches of `batch_size`. The last batch
+    /// holds the remaining rows, or is left out if `drop_last` is set.
+    pub fn batches(&self, batch_size: usize, drop_last: bool) -> Result<Vec<Matrix<T>>, NnError> {
+        if batch_size == 0 {
+            return Err(NnError::EmptyDimension);
         }
+        let indices: Vec<usize> = (0..self.rows).collect();
+        return indices.chunks(batch_size)
+            .filter(|batch| !drop_last || batch.len() == batch_size)
+            .map(|batch| self.select_rows(batch))
+            .collect();
     }
-
-    pub fn scalar_addition(matrix: Matrix, scalar: f32) -> Matrix {
-        let mut values = Vec::with_capacity(matrix.rows);
-        for i in 0..matrix.rows {
-            values.push(Vec::with_capacity(matrix.cols));
-            for j in 0..matrix.cols {
-                values[i].push(matrix.values[i][j] + scalar);
+    /// Stacks matrices with the same number of columns on top of each other.
+    pub fn concat_rows(matrices: &[Matrix<T>]) -> Result<Matrix<T>, NnError> {
+        let first = matrices.first().ok_or(NnError::EmptyDimension)?;
+        let mut values = Vec::with_capacity(matrices.iter().map(|m| m.values.len()).sum());
+        for matrix in matrices {
+            if matrix.cols != first.cols {
+                return Err(NnError::ShapeMismatch { op: "stack", left: first.shape(), right: matrix.shape() });
             }
+            values.extend_from_slice(&matrix.values);
         }
-        return Matrix { values, rows: matrix.rows, cols: matrix.cols };
+        let rows = values.len() / first.cols;
+        return Ok(Matrix { values, rows, cols: first.cols });
     }
-    pub fn matrix_multiplication(matrix: &Matrix, matrix2: &Matrix) -> Result<Matrix, String> {
-        if matrix.cols != matrix2.rows {
-            return Err("The column count of the left matrix is not equal to the row count of the right matrix.".parse().unwrap());
-        }
-        let rows = matrix.rows;
-        let cols = matrix2.cols;
-
-        let mut values = Vec::with_capacity(rows);
-
-        for i in 0..rows {
-            values.push(Vec::with_capacity(cols));
-            for j in 0..cols {
-                values[i].push(0.0);
-                for k in 0..matrix.cols {
-                    values[i][j] += matrix.values[i][k] * matrix2.values[k][j];
+    /// The column of the largest value in every row.
+    pub fn argmax_rows(&self) -> Vec<usize> {
+        return (0..self.rows).map(|i| {
+            let row = self.row(i);
+            let mut best = 0;
+            for (j, x) in row.iter().enumerate() {
+                if *x > row[best] {
+                    best = j;
                 }
             }
-        }
-        return Ok(Matrix { values, rows: matrix.rows, cols: matrix2.cols });
+            best
+        }).collect();
     }
-    pub fn matrix_component_multiplication_mut(&mut self, matrix2: &Matrix) {
+    /// Adds all rows together into a single row.
+    pub fn sum_rows(&self) -> Matrix<T> {
+        let mut values = vec![T::zero(); self.cols];
         for i in 0..self.rows {
-            for j in 0..self.cols {
-                self.values[i][j] *= matrix2.values[i][j];
+            for (x, y) in values.iter_mut().zip(self.row(i)) {
+                *x += *y;
             }
         }
+        return Matrix { values, rows: 1, cols: self.cols };
+    }
+    pub fn get_single_col(&self, i: usize) -> Matrix<T> {
+        let values = (0..self.rows).map(|row| self.get(row, i)).collect();
+        return Matrix { values, rows: self.rows, cols: 1 };
+    }
+    pub fn get_single_row(&self, i: usize) -> Matrix<T> {
+        return Matrix { values: self.row(i).to_vec(), rows: 1, cols: self.cols };
     }
-    pub fn matrix_component_multiplication(matrix: &Matrix, matrix2: &Matrix) -> Result<Matrix, String> {
-        let mut values = Vec::with_capacity(matrix.rows);
 
-        for i in 0..matrix.rows {
-            values.push(Vec::with_capacity(matrix.cols));
-            for j in 0..matrix.cols {
-                values[i].push(matrix.values[i][j] * matrix2.values[i][j]);
-            }
+    pub fn scalar_multiplication(matrix: &Matrix<T>, scalar: T) -> Matrix<T> {
+        return matrix.apply_function(&|x| x * scalar);
+    }
+    pub fn scalar_multiplication_mut(&mut self, scalar: T) {
+        for x in self.values.iter_mut() {
+            *x *= scalar;
         }
-        return Ok(Matrix { values, rows: matrix.rows, cols: matrix.cols });
     }
 
-
-    pub fn matrix_addition(matrix: &Matrix, matrix2: &Matrix) -> Result<Matrix, String> {
-        if matrix.rows != matrix2.rows || matrix.cols != matrix2.cols {
-            return Err("The dimensions of the two matrices do not match.".parse().unwrap());
+    pub fn scalar_addition(matrix: Matrix<T>, scalar: T) -> Matrix<T> {
+        return matrix.apply_function(&|x| x + scalar);
+    }
+    pub fn matrix_multiplication(matrix: &Matrix<T>, matrix2: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        if matrix.cols != matrix2.rows {
+            return Err(NnError::ShapeMismatch { op: "multiply", left: matrix.shape(), right: matrix2.shape() });
         }
-        let mut values = Vec::with_capacity(matrix.rows);
-        for i in 0..matrix.rows {
-            values.push(Vec::with_capacity(matrix.cols));
-            for j in 0..matrix.cols {
-                values[i].push(matrix.values[i][j] + matrix2.values[i][j]);
-            }
+        let values = gemm::multiply(
+            View::new(&matrix.values, matrix.cols),
+            View::new(&matrix2.values, matrix2.cols),
+            matrix.rows, matrix.cols, matrix2.cols);
+        return Ok(Matrix { values, rows: matrix.rows, cols: matrix2.cols });
+    }
+    /// Computes `matrixᵀ * matrix2` without building the transposed copy.
+    pub fn matrix_multiplication_transposed_left(matrix: &Matrix<T>, matrix2: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        if matrix.rows != matrix2.rows {
+            return Err(NnError::ShapeMismatch { op: "multiply the transpose of", left: matrix.shape(), right: matrix2.shape() });
         }
-        return Ok(Matrix {
-            values,
-            rows: matrix.rows,
-            cols: matrix.cols,
-        });
-    }
-    pub fn matrix_addition_mut(&mut self, matrix2: &Matrix) {
-        if self.rows != matrix2.rows || self.cols != matrix2.cols {
-            panic!("The dimensions of the two matrices do not match.");
+        let values = gemm::multiply(
+            View::transposed(&matrix.values, matrix.cols),
+            View::new(&matrix2.values, matrix2.cols),
+            matrix.cols, matrix.rows, matrix2.cols);
+        return Ok(Matrix { values, rows: matrix.cols, cols: matrix2.cols });
+    }
+    /// Computes `matrix * matrix2ᵀ` without building the transposed copy.
+    pub fn matrix_multiplication_transposed_right(matrix: &Matrix<T>, matrix2: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        if matrix.cols != matrix2.cols {
+            return Err(NnError::ShapeMismatch { op: "multiply by the transpose of", left: matrix.shape(), right: matrix2.shape() });
         }
-        for i in 0..self.rows {
-            for j in 0..self.cols {
-                self.values[i][j] += matrix2.values[i][j];
-            }
+        let values = gemm::multiply(
+            View::new(&matrix.values, matrix.cols),
+            View::transposed(&matrix2.values, matrix2.cols),
+            matrix.rows, matrix.cols, matrix2.rows);
+        return Ok(Matrix { values, rows: matrix.rows, cols: matrix2.rows });
+    }
+    /// The unblocked, single threaded product that the fast kernels are checked against.
+    pub fn matrix_multiplication_naive(matrix: &Matrix<T>, matrix2: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        if matrix.cols != matrix2.rows {
+            return Err(NnError::ShapeMismatch { op: "multiply", left: matrix.shape(), right: matrix2.shape() });
+        }
+        let values = gemm::multiply_naive(
+            View::new(&matrix.values, matrix.cols),
+            View::new(&matrix2.values, matrix2.cols),
+            matrix.rows, matrix.cols, matrix2.cols);
+        return Ok(Matrix { values, rows: matrix.rows, cols: matrix2.cols });
+    }
+    pub fn matrix_component_multiplication_mut(&mut self, matrix2: &Matrix<T>) -> Result<(), NnError> {
+        Matrix::check_same_shape("multiply the components of", self, matrix2)?;
+        for (x, y) in self.values.iter_mut().zip(&matrix2.values) {
+            *x *= *y;
         }
+        return Ok(());
+    }
+    pub fn matrix_component_multiplication(matrix: &Matrix<T>, matrix2: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        let mut result = matrix.clone();
+        result.matrix_component_multiplication_mut(matrix2)?;
+        return Ok(result);
     }
 
 
-    pub fn matrix_subtraction(matrix: &Matrix, matrix2: &Matrix) -> Result<Matrix, String> {
-        if matrix.rows != matrix2.rows || matrix.cols != matrix2.cols {
-            return Err("The dimensions of the two matrices do not match.".parse().unwrap());
-        }
-        let mut values = Vec::with_capacity(matrix.rows);
-        for i in 0..matrix.rows {
-            values.push(Vec::with_capacity(matrix.cols));
-            for j in 0..matrix.cols {
-                values[i].push(matrix.values[i][j] - matrix2.values[i][j]);
-            }
+    pub fn matrix_addition(matrix: &Matrix<T>, matrix2: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        let mut result = matrix.clone();
+        result.matrix_addition_mut(matrix2)?;
+        return Ok(result);
+    }
+    pub fn matrix_addition_mut(&mut self, matrix2: &Matrix<T>) -> Result<(), NnError> {
+        Matrix::check_same_shape("add", self, matrix2)?;
+        for (x, y) in self.values.iter_mut().zip(&matrix2.values) {
+            *x += *y;
         }
-        return Ok(Matrix {
-            values,
-            rows: matrix.rows,
-            cols: matrix.cols,
-        });
+        return Ok(());
     }
 
 
-    pub fn matrix_subtraction_mut(&mut self, matrix2: &Matrix) {
-        if self.rows != matrix2.rows || self.cols != matrix2.cols {
-            panic!("The dimensions of the two matrices do not match.");
-        }
-        for i in 0..self.rows {
-            for j in 0..self.cols {
-                self.values[i][j] -= matrix2.values[i][j];
-            }
+    pub fn matrix_subtraction(matrix: &Matrix<T>, matrix2: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        let mut result = matrix.clone();
+        result.matrix_subtraction_mut(matrix2)?;
+        return Ok(result);
+    }
+
+
+    pub fn matrix_subtraction_mut(&mut self, matrix2: &Matrix<T>) -> Result<(), NnError> {
+        Matrix::check_same_shape("subtract", self, matrix2)?;
+        for (x, y) in self.values.iter_mut().zip(&matrix2.values) {
+            *x -= *y;
         }
+        return Ok(());
     }
 
-    pub fn transpose(&self) -> Matrix {
-        let mut values = Vec::with_capacity(self.cols);
+    pub fn transpose(&self) -> Matrix<T> {
+        let mut values = Vec::with_capacity(self.values.len());
         for i in 0..self.cols {
-            values.push(Vec::with_capacity(self.rows));
             for j in 0..self.rows {
-                values[i].push(self.values[j][i]);
+                values.push(self.get(j, i));
             }
         }
         return Matrix { values, rows: self.cols, cols: self.rows };
     }
-    pub fn apply_function(&self, function: &dyn Fn(f32) -> f32) -> Matrix {
-        let mut values = Vec::with_capacity(self.rows);
-        for i in 0..self.rows {
-            values.push(Vec::with_capacity(self.cols));
-            for j in 0..self.cols {
-                values[i].push(function(self.values[i][j]));
-            }
-        }
+    pub fn apply_function(&self, function: &dyn Fn(T) -> T) -> Matrix<T> {
+        let values = self.values.iter().map(|&x| function(x)).collect();
+        return Matrix { values, rows: self.rows, cols: self.cols };
+    }
+    /// Converts every value to another precision.
+    pub fn cast<U: Float>(&self) -> Matrix<U> {
+        let values = self.values.iter().map(|x| U::from_f64(x.to_f64())).collect();
         return Matrix { values, rows: self.rows, cols: self.cols };
     }
 }
 
-impl Debug for Matrix {
+impl<T: Float> Debug for Matrix<T> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self.values)
+        f.debug_list().entries(self.values.chunks(self.cols)).finish()
     }
 }
 
-impl Clone for Matrix {
-    fn clone(&self) -> Self {
-        let mut values = Vec::with_capacity(self.rows);
-        for i in 0..self.rows {
-            values.push(Vec::with_capacity(self.cols));
-            for j in 0..self.cols {
-                values[i].push(self.values[i][j]);
-            }
-        }
-        return Matrix { values, rows: self.rows, cols: self.cols };
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counting(rows: usize) -> Matrix<f64> {
+        Matrix::from_flat(rows, 2, (0..2 * rows).map(|x| x as f64).collect()).unwrap()
+    }
+
+    #[test]
+    fn selecting_a_missing_row_is_an_error() {
+        let matrix = counting(3);
+        assert_eq!(matrix.select_rows(&[2, 0]).unwrap().values(), &[4.0, 5.0, 0.0, 1.0]);
+        assert!(matches!(matrix.select_rows(&[1, 3]), Err(NnError::RowOutOfRange { row: 3, rows: 3 })));
+        assert!(matches!(matrix.select_rows(&[]), Err(NnError::EmptyDimension)));
+    }
+
+    #[test]
+    fn batches_keep_or_drop_the_partial_last_batch() {
+        let matrix = counting(5);
+        let kept = matrix.batches(2, false).unwrap();
+        assert_eq!(kept.iter().map(Matrix::rows).collect::<Vec<_>>(), vec![2, 2, 1]);
+        assert_eq!(kept[2].values(), &[8.0, 9.0]);
+        let dropped = matrix.batches(2, true).unwrap();
+        assert_eq!(dropped.iter().map(Matrix::rows).collect::<Vec<_>>(), vec![2, 2]);
+        assert_eq!(Matrix::concat_rows(&dropped).unwrap().values(), &matrix.values()[..8]);
+        // A batch larger than the matrix is the partial last batch.
+        assert_eq!(matrix.batches(8, false).unwrap().len(), 1);
+        assert!(matrix.batches(8, true).unwrap().is_empty());
+        assert!(matches!(matrix.batches(0, false), Err(NnError::EmptyDimension)));
     }
 }
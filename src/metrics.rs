@@ -0,0 +1,320 @@
+use std::fmt::{Display, Formatter};
+
+use crate::error::NnError;
+use crate::float::Float;
+use crate::loss::Loss;
+use crate::matrix::Matrix;
+use crate::network::Network;
+
+/// Class labels, given either as one index per sample or as one-hot rows.
+#[derive(Clone, Copy, Debug)]
+pub enum Labels<'a, T: Float = f32> {
+    Indices(&'a [usize]),
+    OneHot(&'a Matrix<T>),
+}
+
+impl<'a, T: Float> Labels<'a, T> {
+    /// The class index of every sample. One-hot rows map to their largest column.
+    pub fn indices(&self) -> Vec<usize> {
+        match self {
+            Labels::Indices(indices) => indices.to_vec(),
+            Labels::OneHot(matrix) => matrix.argmax_rows(),
+        }
+    }
+}
+
+/// Checks that there is one label for every row of outputs and that every label names a column.
+fn checked_labels<T: Float>(outputs: &Matrix<T>, labels: Labels<T>) -> Result<Vec<usize>, NnError> {
+    let labels = labels.indices();
+    if labels.len() != outputs.rows() {
+        return Err(NnError::LengthMismatch { expected: outputs.rows(), found: labels.len() });
+    }
+    if let Some(&label) = labels.iter().find(|&&label| label >= outputs.cols()) {
+        return Err(NnError::LabelOutOfRange { label, classes: outputs.cols() });
+    }
+    return Ok(labels);
+}
+
+/// The fraction of samples whose largest output is at their label.
+pub fn accuracy<T: Float>(outputs: &Matrix<T>, labels: Labels<T>) -> Result<f64, NnError> {
+    return top_k_accuracy(outputs, labels, 1);
+}
+
+/// The fraction of samples whose label is among their `k` largest outputs.
+pub fn top_k_accuracy<T: Float>(outputs: &Matrix<T>, labels: Labels<T>, k: usize) -> Result<f64, NnError> {
+    let labels = checked_labels(outputs, labels)?;
+    if labels.is_empty() {
+        return Err(NnError::EmptyDimension);
+    }
+    let hits = labels.iter().enumerate().filter(|&(i, &label)| {
+        let row = outputs.row(i);
+        // The label is in the top k if fewer than k outputs beat it; ties go to the lower column.
+        let better = row.iter().enumerate().filter(|&(j, &x)| x > row[label] || (x == row[label] && j < label)).count();
+        better < k
+    }).count();
+    return Ok(hits as f64 / labels.len() as f64);
+}
+
+/// Counts of every pair of actual and predicted class, with one row per actual
+/// class and one column per predicted class.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfusionMatrix {
+    classes: usize,
+    counts: Vec<usize>,
+}
+
+impl ConfusionMatrix {
+    pub fn new(classes: usize) -> ConfusionMatrix {
+        ConfusionMatrix { classes, counts: vec![0; classes * classes] }
+    }
+    /// Tallies the argmax predictions of a batch of outputs against their labels.
+    pub fn from_outputs<T: Float>(outputs: &Matrix<T>, labels: Labels<T>) -> Result<ConfusionMatrix, NnError> {
+        let mut confusion = ConfusionMatrix::new(outputs.cols());
+        confusion.add_outputs(outputs, labels)?;
+        return Ok(confusion);
+    }
+    /// Tallies another batch, so that a data set can be evaluated batch by batch.
+    pub fn add_outputs<T: Float>(&mut self, outputs: &Matrix<T>, labels: Labels<T>) -> Result<(), NnError> {
+        if outputs.cols() != self.classes {
+            return Err(NnError::LengthMismatch { expected: self.classes, found: outputs.cols() });
+        }
+        let labels = checked_labels(outputs, labels)?;
+        for (actual, predicted) in labels.into_iter().zip(outputs.argmax_rows()) {
+            self.add(actual, predicted)?;
+        }
+        return Ok(());
+    }
+    pub fn add(&mut self, actual: usize, predicted: usize) -> Result<(), NnError> {
+        for label in [actual, predicted] {
+            if label >= self.classes {
+                return Err(NnError::LabelOutOfRange { label, classes: self.classes });
+            }
+        }
+        self.counts[actual * self.classes + predicted] += 1;
+        return Ok(());
+    }
+
+    pub fn classes(&self) -> usize {
+        self.classes
+    }
+    /// How often a sample of class `actual` was predicted as `predicted`.
+    pub fn get(&self, actual: usize, predicted: usize) -> usize {
+        self.counts[actual * self.classes + predicted]
+    }
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+    pub fn correct(&self) -> usize {
+        (0..self.classes).map(|c| self.get(c, c)).sum()
+    }
+    pub fn accuracy(&self) -> f64 {
+        ratio(self.correct(), self.total())
+    }
+
+    fn true_positives(&self, class: usize) -> usize {
+        self.get(class, class)
+    }
+    /// Samples predicted as `class`, correctly or not.
+    fn predicted(&self, class: usize) -> usize {
+        (0..self.classes).map(|actual| self.get(actual, class)).sum()
+    }
+    /// Samples that belong to `class`.
+    fn actual(&self, class: usize) -> usize {
+        (0..self.classes).map(|predicted| self.get(class, predicted)).sum()
+    }
+
+    /// The fraction of predictions of `class` that were right, or 0 if it was never predicted.
+    pub fn precision(&self, class: usize) -> f64 {
+        ratio(self.true_positives(class), self.predicted(class))
+    }
+    /// The fraction of samples of `class` that were found, or 0 if there were none.
+    pub fn recall(&self, class: usize) -> f64 {
+        ratio(self.true_positives(class), self.actual(class))
+    }
+    pub fn f1(&self, class: usize) -> f64 {
+        f1(self.precision(class), self.recall(class))
+    }
+
+    /// The unweighted mean of the per-class precisions.
+    pub fn macro_precision(&self) -> f64 {
+        self.mean_over_classes(&|c| self.precision(c))
+    }
+    pub fn macro_recall(&self) -> f64 {
+        self.mean_over_classes(&|c| self.recall(c))
+    }
+    pub fn macro_f1(&self) -> f64 {
+        self.mean_over_classes(&|c| self.f1(c))
+    }
+    /// Precision over the pooled counts of all classes.
+    pub fn micro_precision(&self) -> f64 {
+        let predicted = (0..self.classes).map(|c| self.predicted(c)).sum();
+        ratio(self.correct(), predicted)
+    }
+    pub fn micro_recall(&self) -> f64 {
+        let actual = (0..self.classes).map(|c| self.actual(c)).sum();
+        ratio(self.correct(), actual)
+    }
+    pub fn micro_f1(&self) -> f64 {
+        f1(self.micro_precision(), self.micro_recall())
+    }
+
+    fn mean_over_classes(&self, metric: &dyn Fn(usize) -> f64) -> f64 {
+        if self.classes == 0 {
+            return 0.0;
+        }
+        return (0..self.classes).map(metric).sum::<f64>() / self.classes as f64;
+    }
+
+    /// A table of precision, recall, F1 and support for every class, followed
+    /// by the macro and micro averages.
+    pub fn report(&self) -> String {
+        let mut report = format!("{:>8} {:>9} {:>9} {:>9} {:>9}\n", "class", "precision", "recall", "f1", "support");
+        for c in 0..self.classes {
+            report += &format!("{:>8} {:>9.4} {:>9.4} {:>9.4} {:>9}\n", c, self.precision(c), self.recall(c), self.f1(c), self.actual(c));
+        }
+        report += &format!("{:>8} {:>9.4} {:>9.4} {:>9.4} {:>9}\n", "macro", self.macro_precision(), self.macro_recall(), self.macro_f1(), self.total());
+        report += &format!("{:>8} {:>9.4} {:>9.4} {:>9.4} {:>9}\n", "micro", self.micro_precision(), self.micro_recall(), self.micro_f1(), self.total());
+        return report;
+    }
+}
+
+/// The results of running a network over a labelled data set.
+#[derive(Clone, Debug)]
+pub struct Evaluation {
+    /// The loss averaged over every sample.
+    pub loss: f64,
+    pub accuracy: f64,
+    pub confusion: ConfusionMatrix,
+    /// The rows whose argmax prediction did not match their target.
+    pub misclassified: Vec<usize>,
+}
+
+/// Runs the network over `inputs` in batches of `batch_size` rows and compares
+/// its outputs with the one-hot `targets`.
+pub fn evaluate<T: Float>(network: &Network<T>, inputs: &Matrix<T>, targets: &Matrix<T>, loss: &dyn Loss<T>, batch_size: usize) -> Result<Evaluation, NnError> {
+    if inputs.rows() != targets.rows() {
+        return Err(NnError::ShapeMismatch { op: "evaluate on", left: inputs.shape(), right: targets.shape() });
+    }
+    if batch_size == 0 || inputs.rows() == 0 {
+        return Err(NnError::EmptyDimension);
+    }
+    let mut confusion = ConfusionMatrix::new(targets.cols());
+    let mut misclassified = Vec::new();
+    let mut loss_sum = 0.0;
+    let order: Vec<usize> = (0..inputs.rows()).collect();
+    for batch in order.chunks(batch_size) {
+        let batch_targets = targets.select_rows(batch)?;
+        let outputs = network.feedforward(inputs.select_rows(batch)?)?;
+        let output = outputs.last().unwrap();
+        loss_sum += loss.value(&batch_targets, output)?.to_f64() * batch.len() as f64;
+        confusion.add_outputs(output, Labels::OneHot(&batch_targets))?;
+        for ((&row, predicted), actual) in batch.iter().zip(output.argmax_rows()).zip(batch_targets.argmax_rows()) {
+            if predicted != actual {
+                misclassified.push(row);
+            }
+        }
+    }
+    return Ok(Evaluation {
+        loss: loss_sum / inputs.rows() as f64,
+        accuracy: confusion.accuracy(),
+        confusion,
+        misclassified,
+    });
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 { 0.0 } else { numerator as f64 / denominator as f64 }
+}
+
+fn f1(precision: f64, recall: f64) -> f64 {
+    if precision + recall == 0.0 { 0.0 } else { 2.0 * precision * recall / (precision + recall) }
+}
+
+/// Prints the counts as a grid with actual classes down the side and predicted classes across the top.
+impl Display for ConfusionMatrix {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let largest = self.counts.iter().copied().max().unwrap_or(0).max(self.classes.saturating_sub(1));
+        let width = largest.to_string().len() + 1;
+        write!(f, "{:>w$} |", "a\\p", w = width.max(4))?;
+        for predicted in 0..self.classes {
+            write!(f, "{:>w$}", predicted, w = width)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{}", "-".repeat(width.max(4) + 2 + width * self.classes))?;
+        for actual in 0..self.classes {
+            write!(f, "{:>w$} |", actual, w = width.max(4))?;
+            for predicted in 0..self.classes {
+                write!(f, "{:>w$}", self.get(actual, predicted), w = width)?;
+            }
+            writeln!(f)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(found: f64, expected: f64) {
+        assert!((found - expected).abs() < 1e-12, "{} != {}", found, expected);
+    }
+
+    #[test]
+    fn top_k_accuracy_breaks_ties_towards_the_lower_column() {
+        let outputs: Matrix<f64> = Matrix::from_values(vec![
+            vec![0.1, 0.5, 0.2, 0.2],
+            vec![0.3, 0.3, 0.3, 0.1],
+            vec![0.3, 0.3, 0.3, 0.1],
+            vec![0.9, 0.0, 0.0, 0.1],
+        ]).unwrap();
+        // Outputs above the label, or equal to it in a lower column: 1, 2, 0 and 1.
+        let labels = [2, 2, 0, 3];
+        assert_close(accuracy(&outputs, Labels::Indices(&labels)).unwrap(), 0.25);
+        assert_close(top_k_accuracy(&outputs, Labels::Indices(&labels), 2).unwrap(), 0.75);
+        assert_close(top_k_accuracy(&outputs, Labels::Indices(&labels), 3).unwrap(), 1.0);
+        let one_hot = Matrix::from_flat(4, 4, labels.iter().flat_map(|&label| (0..4).map(move |c| if c == label { 1.0 } else { 0.0 })).collect()).unwrap();
+        assert_close(top_k_accuracy(&outputs, Labels::OneHot(&one_hot), 2).unwrap(), 0.75);
+
+        assert!(matches!(accuracy(&outputs, Labels::Indices(&[2, 2, 0])), Err(NnError::LengthMismatch { expected: 4, found: 3 })));
+        assert!(matches!(accuracy(&outputs, Labels::Indices(&[2, 2, 0, 4])), Err(NnError::LabelOutOfRange { label: 4, classes: 4 })));
+    }
+
+    #[test]
+    fn confusion_matrix_scores_every_class() {
+        // Class 2 is never predicted and class 3 never occurs.
+        let mut confusion = ConfusionMatrix::new(4);
+        for &(actual, predicted) in &[(0, 0), (0, 0), (0, 1), (1, 1), (1, 3), (2, 0)] {
+            confusion.add(actual, predicted).unwrap();
+        }
+        assert_eq!(confusion.total(), 6);
+        assert_eq!(confusion.correct(), 3);
+        assert_close(confusion.accuracy(), 0.5);
+
+        let expected = [(2.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0), (0.5, 0.5, 0.5), (0.0, 0.0, 0.0), (0.0, 0.0, 0.0)];
+        for (class, &(precision, recall, f1)) in expected.iter().enumerate() {
+            assert_close(confusion.precision(class), precision);
+            assert_close(confusion.recall(class), recall);
+            assert_close(confusion.f1(class), f1);
+        }
+        // (2/3 + 1/2 + 0 + 0) / 4
+        assert_close(confusion.macro_precision(), 7.0 / 24.0);
+        assert_close(confusion.macro_recall(), 7.0 / 24.0);
+        assert_close(confusion.macro_f1(), 7.0 / 24.0);
+        assert_close(confusion.micro_precision(), 0.5);
+        assert_close(confusion.micro_recall(), 0.5);
+        assert_close(confusion.micro_f1(), 0.5);
+
+        assert!(matches!(confusion.add(4, 0), Err(NnError::LabelOutOfRange { label: 4, classes: 4 })));
+    }
+
+    #[test]
+    fn empty_counts_score_zero() {
+        let confusion = ConfusionMatrix::new(2);
+        for score in [confusion.accuracy(), confusion.precision(0), confusion.recall(1), confusion.f1(0),
+                      confusion.macro_f1(), confusion.micro_precision(), confusion.micro_recall(), confusion.micro_f1()] {
+            assert_eq!(score, 0.0);
+        }
+        assert_eq!(ConfusionMatrix::new(0).macro_precision(), 0.0);
+    }
+}
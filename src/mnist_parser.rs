@@ -1,79 +1,118 @@
-use std::fs::File;
-use std::io;
-use std::io::Read;
+use std::path::Path;
 
+use crate::dataset::Dataset;
+use crate::error::NnError;
+use crate::float::Float;
+use crate::idx::IdxArray;
 use crate::matrix::Matrix;
 
-struct ImageHeader {
-    magic_number: u32,
-    number_of_images: u32,
-    number_of_rows: u32,
-    number_of_cols: u32,
-}
+const LABEL_MAGIC_NUMBER: u32 = 0x0000_0801;
+const IMAGE_MAGIC_NUMBER: u32 = 0x0000_0803;
+const NUMBER_OF_CLASSES: usize = 10;
 
-struct LabelHeader {
-    magic_number: u32,
-    number_of_items: u32,
+/// The magic number of an IDX file: its data type byte followed by its number of dimensions.
+fn magic_number(array: &IdxArray) -> u32 {
+    ((array.data_type.code() as u32) << 8) | array.dimensions.len() as u32
 }
 
-fn read_f32(f: &mut File) -> io::Result<f32> {
-    Ok(read_u8(f)? as f32)
+fn check_magic_number(expected: u32, found: u32) -> Result<(), NnError> {
+    if expected != found {
+        return Err(NnError::BadMagic { expected, found });
+    }
+    Ok(())
 }
 
-fn read_u8(f: &mut File) -> io::Result<u8> {
-    let mut buf = [0u8; 1];
-    f.read_exact(&mut buf)?;
-    Ok(u8::from_be_bytes(buf))
+/// Reads an IDX file of `u8` values with the given magic number.
+fn read_u8_idx<P: AsRef<Path>>(path: P, magic: u32) -> Result<IdxArray, NnError> {
+    let array = IdxArray::read(path)?;
+    check_magic_number(magic, magic_number(&array))?;
+    return Ok(array);
 }
 
-fn read_u32(f: &mut File) -> io::Result<u32> {
-    let mut buf = [0u8; 4];
-    f.read_exact(&mut buf)?;
-    Ok(u32::from_be_bytes(buf))
+/// Loads one-hot labels of the ten digits, one row per label.
+pub fn load_labels<T: Float, P: AsRef<Path>>(path: P) -> Result<Matrix<T>, NnError> {
+    return load_labels_with_classes(path, NUMBER_OF_CLASSES);
 }
 
-pub fn get_labels(path: String, batch_size: usize) -> io::Result<Vec<Matrix>>{
-    let mut f = File::open(path).expect("could not open the file.");
+/// Like `load_labels`, for label sets with another number of classes than the
+/// ten digits, such as EMNIST.
+pub fn load_labels_with_classes<T: Float, P: AsRef<Path>>(path: P, classes: usize) -> Result<Matrix<T>, NnError> {
+    let labels = load_label_indices(path, classes)?;
+    let mut values = vec![T::zero(); labels.len() * classes];
+    for (i, &label) in labels.iter().enumerate() {
+        values[i * classes + label] = T::one();
+    }
+    return Matrix::from_flat(labels.len(), classes, values);
+}
 
-    let image_header = LabelHeader {
-        magic_number: read_u32(&mut f)?,
-        number_of_items: read_u32(&mut f)?,
-    };
-    let mut result = Vec::with_capacity(image_header.number_of_items as usize / batch_size);
-    for i in 0..image_header.number_of_items / batch_size as u32 {
-        let mut values = Vec::with_capacity(batch_size);
-        for k in 0..batch_size{
-            values.push(vec![0.0; 10]);
-            values.last_mut().unwrap()[read_u8(&mut f)? as usize] = 1.0;
+/// Loads the labels as class indices, checking that each is below `classes`.
+pub fn load_label_indices<P: AsRef<Path>>(path: P, classes: usize) -> Result<Vec<usize>, NnError> {
+    let labels = read_u8_idx(path, LABEL_MAGIC_NUMBER)?;
+    let mut indices = Vec::with_capacity(labels.len());
+    for label in labels.values() {
+        let label = label as usize;
+        if label >= classes {
+            return Err(NnError::LabelOutOfRange { label, classes });
         }
-        result.push(Matrix::from_values(values).expect("Test"));
+        indices.push(label);
     }
+    return Ok(indices);
+}
 
-    Ok(result)
+/// Loads images with one row per image and the pixels scaled into [0, 1].
+pub fn load_images<T: Float, P: AsRef<Path>>(path: P) -> Result<Matrix<T>, NnError> {
+    let images = read_u8_idx(path, IMAGE_MAGIC_NUMBER)?;
+    return images.to_matrix(1.0 / 255.0);
 }
 
-pub fn get_input_vec(path: String, batch_size: usize) -> io::Result<Vec<Matrix>> {
-    let mut f = File::open(path).expect("could not open the file.");
+/// MNIST as a `Dataset`: the scaled pixels of an image as the input and its
+/// one-hot label as the target. The labels are kept as indices and only
+/// expanded when a sample is read.
+#[derive(Clone, Debug)]
+pub struct MnistDataset<T: Float = f32> {
+    images: Matrix<T>,
+    labels: Vec<usize>,
+    classes: usize,
+}
 
-    let image_header = ImageHeader {
-        magic_number: read_u32(&mut f)?,
-        number_of_images: read_u32(&mut f)?,
-        number_of_rows: read_u32(&mut f)?,
-        number_of_cols: read_u32(&mut f)?,
-    };
-    let mut result = Vec::with_capacity(image_header.number_of_images as usize / batch_size);
-    for i in 0..image_header.number_of_images / batch_size as u32 {
-        let image_size = (image_header.number_of_rows * image_header.number_of_cols) as usize;
-        let mut values = Vec::with_capacity(image_size);
-        for k in 0..batch_size{
-            values.push(vec![]);
-            for j in 0..image_size {
-                values[k].push(read_f32(&mut f).expect("Test") / 255.0)
-            }
+impl<T: Float> MnistDataset<T> {
+    pub fn load<P: AsRef<Path>, Q: AsRef<Path>>(images: P, labels: Q) -> Result<MnistDataset<T>, NnError> {
+        return MnistDataset::load_with_classes(images, labels, NUMBER_OF_CLASSES);
+    }
+    pub fn load_with_classes<P: AsRef<Path>, Q: AsRef<Path>>(images: P, labels: Q, classes: usize) -> Result<MnistDataset<T>, NnError> {
+        let images = load_images(images)?;
+        let labels = load_label_indices(labels, classes)?;
+        if images.rows() != labels.len() {
+            return Err(NnError::LengthMismatch { expected: images.rows(), found: labels.len() });
         }
-
-        result.push(Matrix::from_values(values).expect("Test"));
+        return Ok(MnistDataset { images, labels, classes });
     }
+    pub fn images(&self) -> &Matrix<T> {
+        &self.images
+    }
+    pub fn labels(&self) -> &[usize] {
+        &self.labels
+    }
+    /// Splits off the samples from `at` on, as for a validation set. Both
+    /// parts have to keep at least one sample.
+    pub fn split_off(&mut self, at: usize) -> Result<MnistDataset<T>, NnError> {
+        if at == 0 || at >= self.labels.len() {
+            return Err(NnError::EmptyDimension);
+        }
+        let rows: Vec<usize> = (0..self.labels.len()).collect();
+        let rest = self.images.select_rows(&rows[at..])?;
+        self.images = self.images.select_rows(&rows[..at])?;
+        return Ok(MnistDataset { images: rest, labels: self.labels.split_off(at), classes: self.classes });
+    }
+}
 
-    Ok(result)
-}
\ No newline at end of file
+impl<T: Float> Dataset<T> for MnistDataset<T> {
+    fn len(&self) -> usize {
+        self.labels.len()
+    }
+    fn get(&self, i: usize) -> (Vec<T>, Vec<T>) {
+        let mut target = vec![T::zero(); self.classes];
+        target[self.labels[i]] = T::one();
+        (self.images.row(i).to_vec(), target)
+    }
+}
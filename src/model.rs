@@ -0,0 +1,278 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::activation::Activation;
+use crate::binary;
+use crate::csv::Preprocessor;
+use crate::error::NnError;
+use crate::float::Float;
+use crate::network::Network;
+
+/// Identifies a file as a saved model.
+const FORMAT_NAME: &str = "neuralnetwork-model";
+/// The version of the model file format that `save` writes. Bump it whenever
+/// the envelope changes in a way older readers would misread.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The shape of a network: its input size and the size and activation of every layer.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Architecture {
+    pub inputs: usize,
+    pub layers: Vec<LayerSpec>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LayerSpec {
+    pub outputs: usize,
+    pub activation: Activation,
+}
+
+impl Architecture {
+    pub fn of<T: Float>(network: &Network<T>) -> Architecture {
+        Architecture {
+            inputs: network.layers().first().map_or(0, |layer| layer.input_count()),
+            layers: network.layers().iter().map(|layer| LayerSpec { outputs: layer.output_count(), activation: layer.activation }).collect(),
+        }
+    }
+}
+
+/// How a saved network was trained.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TrainingConfig {
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub learning_rate: f64,
+    pub loss: String,
+    pub optimizer: String,
+}
+
+/// Free-form information about a saved network.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    #[serde(default)]
+    pub dataset: Option<String>,
+    /// When the model was saved, filled in by `save` if left empty.
+    #[serde(default)]
+    pub created: Option<String>,
+    /// Final scores such as the validation accuracy, by name.
+    #[serde(default)]
+    pub metrics: BTreeMap<String, f64>,
+}
+
+/// Everything that is saved next to the weights of a network.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    #[serde(default)]
+    pub training: Option<TrainingConfig>,
+    #[serde(default)]
+    pub metadata: Metadata,
+    /// The transform the inputs went through before training, to be applied
+    /// to new inputs the same way.
+    #[serde(default)]
+    pub preprocessor: Option<Preprocessor>,
+}
+
+/// The versioned file layout written by `Network::save`.
+#[derive(Serialize)]
+#[serde(bound = "T: Float")]
+struct EnvelopeRef<'a, T: Float> {
+    format: &'static str,
+    format_version: u32,
+    architecture: Architecture,
+    training: &'a Option<TrainingConfig>,
+    metadata: &'a Metadata,
+    preprocessor: &'a Option<Preprocessor>,
+    network: &'a Network<T>,
+}
+
+#[derive(Deserialize)]
+#[serde(bound = "T: Float")]
+struct Envelope<T: Float> {
+    architecture: Architecture,
+    #[serde(default)]
+    training: Option<TrainingConfig>,
+    #[serde(default)]
+    metadata: Metadata,
+    #[serde(default)]
+    preprocessor: Option<Preprocessor>,
+    network: Network<T>,
+}
+
+impl<T: Float> Network<T> {
+    /// Saves the network as JSON in the versioned model format.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), NnError> {
+        return self.save_with_info(path, &ModelInfo::default());
+    }
+    /// Saves the network together with its training configuration and metadata.
+    pub fn save_with_info<P: AsRef<Path>>(&self, path: P, info: &ModelInfo) -> Result<(), NnError> {
+        let mut metadata = info.metadata.clone();
+        if metadata.created.is_none() {
+            metadata.created = Some(Local::now().to_rfc3339());
+        }
+        let envelope = EnvelopeRef {
+            format: FORMAT_NAME,
+            format_version: FORMAT_VERSION,
+            architecture: Architecture::of(self),
+            training: &info.training,
+            metadata: &metadata,
+            preprocessor: &info.preprocessor,
+            network: self,
+        };
+        // Written next to the destination and renamed, like checkpoints, so
+        // that a failed save cannot leave a truncated model behind.
+        let path = path.as_ref();
+        let temporary = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        serde_json::to_writer(&mut writer, &envelope)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&temporary, path)?;
+        return Ok(());
+    }
+    /// Loads a network written by `save` or `save_binary`, or a bare network written by older versions.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Network<T>, NnError> {
+        return Ok(Network::load_with_info(path)?.0);
+    }
+    /// Loads a network along with what was saved next to it. Bare and binary
+    /// networks come with empty information.
+    pub fn load_with_info<P: AsRef<Path>>(path: P) -> Result<(Network<T>, ModelInfo), NnError> {
+        if binary::is_binary(&path)? {
+            return Ok((Network::load_binary(path)?, ModelInfo::default()));
+        }
+        return Network::from_json(serde_json::from_slice(&fs::read(path)?)?);
+    }
+    /// Decodes a model written by `save`, or a bare network, from its parsed JSON.
+    pub(crate) fn from_json(value: Value) -> Result<(Network<T>, ModelInfo), NnError> {
+        let version = match value.get("format_version") {
+            Some(version) => version.as_u64().ok_or_else(|| NnError::InvalidModel("the format version is not a number".to_string()))?,
+            None if value.get("layers").is_some() => return Ok((serde_json::from_value(value)?, ModelInfo::default())),
+            None => return Err(NnError::InvalidModel("neither a saved model nor a network".to_string())),
+        };
+        if value.get("format").and_then(Value::as_str) != Some(FORMAT_NAME) {
+            return Err(NnError::InvalidModel(format!("the format is not \"{}\"", FORMAT_NAME)));
+        }
+        if version > FORMAT_VERSION as u64 {
+            return Err(NnError::UnsupportedVersion { found: version.min(u32::MAX as u64) as u32, supported: FORMAT_VERSION });
+        }
+        let envelope: Envelope<T> = serde_json::from_value(value)?;
+        if envelope.architecture != Architecture::of(&envelope.network) {
+            return Err(NnError::InvalidModel("the recorded architecture does not match the layers".to_string()));
+        }
+        if let Some(preprocessor) = &envelope.preprocessor {
+            if preprocessor.input_size() != envelope.architecture.inputs {
+                return Err(NnError::InvalidModel("the preprocessor does not produce as many values as the network takes".to_string()));
+            }
+        }
+        return Ok((envelope.network, ModelInfo { training: envelope.training, metadata: envelope.metadata, preprocessor: envelope.preprocessor }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv::CsvLoader;
+    use crate::layer::Layer;
+    use crate::matrix::Matrix;
+
+    fn network() -> Network<f64> {
+        return Network::from_layers(vec![
+            Layer::new(2, 3, Activation::Tanh).unwrap(),
+            Layer::new(3, 2, Activation::Softmax).unwrap(),
+        ]).unwrap();
+    }
+
+    fn path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("model-test-{}-{}.json", std::process::id(), name))
+    }
+
+    /// The file `save` writes for the network, as JSON.
+    fn saved(network: &Network<f64>, name: &str) -> Value {
+        let path = path(name);
+        network.save(&path).unwrap();
+        let value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        return value;
+    }
+
+    fn assert_same(left: &Network<f64>, right: &Network<f64>) {
+        assert_eq!(Architecture::of(left), Architecture::of(right));
+        for (a, b) in left.layers().iter().zip(right.layers()) {
+            assert_eq!(a.weights.values(), b.weights.values());
+            assert_eq!(a.biases.values(), b.biases.values());
+        }
+    }
+
+    #[test]
+    fn round_trips_with_info() {
+        let network = network();
+        let mut info = ModelInfo {
+            training: Some(TrainingConfig { epochs: 3, batch_size: 16, learning_rate: 0.01, loss: "mse".to_string(), optimizer: "adam".to_string() }),
+            ..ModelInfo::default()
+        };
+        info.metadata.dataset = Some("xor".to_string());
+        info.metadata.metrics.insert("accuracy".to_string(), 0.75);
+        let path = path("info");
+        network.save_with_info(&path, &info).unwrap();
+        let (loaded, loaded_info) = Network::<f64>::load_with_info(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_same(&loaded, &network);
+        assert!(loaded_info.metadata.created.is_some());
+        info.metadata.created = loaded_info.metadata.created.clone();
+        assert_eq!(loaded_info, info);
+    }
+
+    #[test]
+    fn rejects_newer_versions_and_inconsistent_architectures() {
+        let network = network();
+        let mut newer = saved(&network, "newer");
+        newer["format_version"] = Value::from(FORMAT_VERSION + 1);
+        assert!(matches!(Network::<f64>::from_json(newer), Err(NnError::UnsupportedVersion { found, supported: FORMAT_VERSION }) if found == FORMAT_VERSION + 1));
+
+        let mut mismatched = saved(&network, "mismatched");
+        mismatched["architecture"]["layers"][1]["activation"] = serde_json::to_value(Activation::Sigmoid).unwrap();
+        assert!(matches!(Network::<f64>::from_json(mismatched), Err(NnError::InvalidModel(_))));
+
+        let mut renamed = saved(&network, "renamed");
+        renamed["format"] = Value::from("something-else");
+        assert!(matches!(Network::<f64>::from_json(renamed), Err(NnError::InvalidModel(_))));
+    }
+
+    #[test]
+    fn loads_bare_networks_in_the_original_format() {
+        // Nested rows, no precision and no activations, as the first version saved them.
+        let text = r#"{"layers":[{"weights":{"values":[[0.5,-1.0],[0.25,2.0]],"rows":2,"cols":2},"biases":{"values":[[0.0,1.5]],"rows":1,"cols":2}}]}"#;
+        let path = path("bare");
+        fs::write(&path, text).unwrap();
+        let (network, info) = Network::<f32>::load_with_info(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(info, ModelInfo::default());
+        let layer = &network.layers()[0];
+        assert_eq!(layer.activation, Activation::Sigmoid);
+        assert_eq!(layer.weights.values(), &[0.5, -1.0, 0.25, 2.0]);
+        assert_eq!(layer.biases.values(), &[0.0, 1.5]);
+    }
+
+    #[test]
+    fn rejects_a_preprocessor_of_the_wrong_size() {
+        let csv = path("preprocessor").with_extension("csv");
+        fs::write(&csv, "a,b,y\n1,2,0\n3,4,1\n").unwrap();
+        let preprocessor = CsvLoader::new(&["y"]).load::<f64, _>(&csv).unwrap().preprocessor().clone();
+        fs::remove_file(&csv).unwrap();
+        assert_eq!(preprocessor.input_size(), 2);
+
+        let mut value = saved(&network(), "preprocessor");
+        value["preprocessor"] = serde_json::to_value(&preprocessor).unwrap();
+        assert_eq!(Network::<f64>::from_json(value.clone()).unwrap().1.preprocessor, Some(preprocessor));
+        value["architecture"]["inputs"] = Value::from(3);
+        value["network"]["layers"][0]["weights"] = serde_json::to_value(Matrix::<f64>::new_zeroed(3, 3).unwrap()).unwrap();
+        assert!(matches!(Network::<f64>::from_json(value), Err(NnError::InvalidModel(reason)) if reason.contains("preprocessor")));
+    }
+}
@@ -1,96 +1,196 @@
 use std::fmt::{Debug, Formatter};
 
+use std::convert::TryFrom;
+
+use crate::activation::Activation;
+use crate::error::NnError;
+use crate::float::{Float, Precision};
+use crate::initializer::Initializer;
 use crate::layer::Layer;
+use crate::loss::Loss;
+use crate::optimizer::{Optimizer, Sgd};
 use crate::matrix::Matrix;
 
-use serde::Serialize;
-use serde::Deserialize;
+use rand::RngCore;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "NetworkRepr<T>", bound = "T: Float")]
+pub struct Network<T: Float = f32> {
+    pub(crate) layers: Vec<Layer<T>>,
+}
+
+/// The serialized form of a network. Files written before the precision was
+/// recorded hold `f32` values.
+#[derive(Deserialize)]
+#[serde(bound = "T: Float")]
+struct NetworkRepr<T: Float> {
+    #[serde(default = "default_precision")]
+    precision: Precision,
+    layers: Vec<Layer<T>>,
+}
+
+fn default_precision() -> Precision {
+    Precision::F32
+}
+
+impl<T: Float> TryFrom<NetworkRepr<T>> for Network<T> {
+    type Error = NnError;
 
-#[derive(Clone, Serialize, Deserialize)]
-pub struct Network {
-    pub(crate) layers: Vec<Layer>,
+    fn try_from(repr: NetworkRepr<T>) -> Result<Self, Self::Error> {
+        if repr.precision != T::PRECISION {
+            return Err(NnError::PrecisionMismatch { expected: T::PRECISION, found: repr.precision });
+        }
+        return Network::from_layers(repr.layers);
+    }
 }
 
-impl Network {
-    pub fn new(layer_sizes: &[usize]) -> Result<Network, String> {
+impl<T: Float> Serialize for Network<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Network", 2)?;
+        state.serialize_field("precision", &T::PRECISION)?;
+        state.serialize_field("layers", &self.layers)?;
+        state.end()
+    }
+}
+
+/// Everything a forward pass computed, kept around for backpropagation.
+#[derive(Clone, Debug)]
+pub struct ForwardPass<T: Float = f32> {
+    /// The input of the network followed by the output of every layer.
+    pub activations: Vec<Matrix<T>>,
+    /// The weighted sums of every layer before its activation was applied.
+    pub pre_activations: Vec<Matrix<T>>,
+}
+
+/// The gradient of the loss with respect to the parameters of one layer.
+#[derive(Clone, Debug)]
+pub struct LayerGradients<T: Float = f32> {
+    pub weights: Matrix<T>,
+    pub biases: Matrix<T>,
+}
+
+impl<T: Float> ForwardPass<T> {
+    /// The prediction of the network.
+    pub fn output(&self) -> &Matrix<T> {
+        self.activations.last().unwrap()
+    }
+}
+
+impl<T: Float> Network<T> {
+    /// Builds a network with randomly initialized layers that all use the same activation.
+    pub fn new(layer_sizes: &[usize], activation: Activation) -> Result<Network<T>, NnError> {
+        return Network::new_with_rng(layer_sizes, activation, &mut rand::thread_rng());
+    }
+    /// Like `new`, but initializes the layers from the given generator.
+    pub fn new_with_rng(layer_sizes: &[usize], activation: Activation, rng: &mut dyn RngCore) -> Result<Network<T>, NnError> {
+        return Network::initialized(layer_sizes, activation, Initializer::default(), Initializer::default(), rng);
+    }
+    /// Builds a network whose layers all use the same activation and the same
+    /// initializers for their weights and biases. Networks that mix them are
+    /// built from `Layer::initialized` and `from_layers`.
+    pub fn initialized(layer_sizes: &[usize], activation: Activation, weights: Initializer, biases: Initializer, rng: &mut dyn RngCore) -> Result<Network<T>, NnError> {
         let mut layers = Vec::new();
         for i in 1..layer_sizes.len() {
-            layers.push(Layer::new(layer_sizes[i - 1], layer_sizes[i])?)
+            layers.push(Layer::initialized(layer_sizes[i - 1], layer_sizes[i], activation, weights, biases, rng)?)
+        }
+        return Network::from_layers(layers);
+    }
+    /// Builds a network from layers whose sizes have to chain together.
+    pub fn from_layers(layers: Vec<Layer<T>>) -> Result<Network<T>, NnError> {
+        if layers.is_empty() {
+            return Err(NnError::EmptyDimension);
+        }
+        for layer in layers.iter() {
+            if layer.biases.shape() != (1, layer.output_count()) {
+                return Err(NnError::ShapeMismatch { op: "add biases to", left: layer.weights.shape(), right: layer.biases.shape() });
+            }
+        }
+        for pair in layers.windows(2) {
+            if pair[0].output_count() != pair[1].input_count() {
+                return Err(NnError::ShapeMismatch { op: "chain", left: pair[0].weights.shape(), right: pair[1].weights.shape() });
+            }
         }
         return Ok(Network { layers });
     }
-    pub fn feedforward(&self, input: Matrix, activaction_function: &dyn Fn(f32) -> f32) -> Result<Vec<Matrix>, String> {
+    /// Returns the input followed by the output of every layer, with the network's prediction last.
+    pub fn feedforward(&self, input: Matrix<T>) -> Result<Vec<Matrix<T>>, NnError> {
         let mut res = vec![input];
         for i in 0..self.layers.len() {
-            res.push(self.get_result_index(i, res.last().unwrap(), activaction_function)?);
+            res.push(self.get_result_index(i, res.last().unwrap())?);
         }
         return Ok(res);
     }
-    pub fn get_result_index(&self, index: usize, input: &Matrix, activation_function: &dyn Fn(f32) -> f32) -> Result<Matrix, String> {
-        return self.layers[index].clone().get_result(input, activation_function);
+    /// Like `feedforward`, but also keeps the pre-activations needed by `backpropagate`.
+    pub fn forward_pass(&self, input: Matrix<T>) -> Result<ForwardPass<T>, NnError> {
+        let mut activations = vec![input];
+        let mut pre_activations = Vec::with_capacity(self.layers.len());
+        for layer in self.layers.iter() {
+            let pre_activation = layer.get_pre_activation(activations.last().unwrap())?;
+            activations.push(layer.activation.forward(&pre_activation));
+            pre_activations.push(pre_activation);
+        }
+        return Ok(ForwardPass { activations, pre_activations });
+    }
+    pub fn get_result_index(&self, index: usize, input: &Matrix<T>) -> Result<Matrix<T>, NnError> {
+        return self.layers[index].get_result(input);
+    }
+    pub fn layers(&self) -> &[Layer<T>] {
+        &self.layers
+    }
+    pub fn layers_mut(&mut self) -> &mut [Layer<T>] {
+        &mut self.layers
+    }
+    /// Runs one batch forward and backward and lets the optimizer update the parameters.
+    pub fn train_batch(&mut self, input: Matrix<T>, expected: &Matrix<T>, loss: &dyn Loss<T>, optimizer: &mut dyn Optimizer<T>, learning_rate: T) -> Result<ForwardPass<T>, NnError> {
+        let pass = self.forward_pass(input)?;
+        let gradients = self.compute_gradients(&pass, expected, loss)?;
+        optimizer.step(&mut self.layers, &gradients, learning_rate)?;
+        return Ok(pass);
     }
 
 
-    pub fn backpropagate(&mut self, result: &Vec<Matrix>, expected: Matrix, derivative_activation_function: &dyn Fn(f32) -> f32, learning_rate: f32) {
-        let mut delta = Matrix::matrix_subtraction(&result.last().unwrap(), &expected).unwrap().transpose();
+    /// Computes the gradient of the loss with respect to the weights and biases of every layer.
+    pub fn compute_gradients(&self, pass: &ForwardPass<T>, expected: &Matrix<T>, loss: &dyn Loss<T>) -> Result<Vec<LayerGradients<T>>, NnError> {
+        if pass.pre_activations.len() != self.layers.len() || self.layers.is_empty() {
+            return Err(NnError::LengthMismatch { expected: self.layers.len(), found: pass.pre_activations.len() });
+        }
+        let result = &pass.activations;
+        let last = self.layers.len() - 1;
+        // The delta is the gradient of the loss with respect to the pre-activations
+        // of a layer. It has one row per sample in the batch and one column per neuron.
+        let mut delta = loss.output_delta(self.layers[last].activation, &pass.pre_activations[last], expected, pass.output())?;
 
+        let mut gradients = Vec::with_capacity(self.layers.len());
         for layer_index in (0..self.layers.len()).rev() {
-            let mut delta_weights =  Matrix::matrix_multiplication(&delta,&result[layer_index]).unwrap().transpose();
-            delta_weights.scalar_multiplication_mut(learning_rate);
+            let weights = Matrix::matrix_multiplication_transposed_left(&result[layer_index], &delta)?;
+            let biases = delta.sum_rows();
 
-            let mut delta_biases =
-                Matrix::new_zeroed(1, self.layers[layer_index].biases.cols).unwrap();
-            for i in 0..expected.rows {
-                delta_biases.matrix_addition_mut(&delta.transpose().get_single_row(i));
+            if layer_index > 0 {
+                let gradient = Matrix::matrix_multiplication_transposed_right(
+                            &delta,
+                            &self.layers[layer_index].weights)?;
+                delta = self.layers[layer_index - 1].activation.backward(
+                    &pass.pre_activations[layer_index - 1], &result[layer_index], &gradient)?;
             }
-            delta_biases.scalar_multiplication_mut(learning_rate / expected.rows as f32);
-
-
-            delta = Matrix::matrix_multiplication(
-                        &self.layers[layer_index].weights,
-                        &delta).unwrap();
-            delta.matrix_component_multiplication_mut(&result[layer_index].apply_function(derivative_activation_function).transpose());
-
-            self.layers.get_mut(layer_index).unwrap().weights.matrix_subtraction_mut(&delta_weights);
-            self.layers.get_mut(layer_index).unwrap().biases.matrix_subtraction_mut(&delta_biases);
+            gradients.push(LayerGradients { weights, biases });
         }
+        gradients.reverse();
+        return Ok(gradients);
+    }
+    /// Applies one step of plain gradient descent.
+    pub fn backpropagate(&mut self, pass: &ForwardPass<T>, expected: &Matrix<T>, loss: &dyn Loss<T>, learning_rate: T) -> Result<(), NnError> {
+        let gradients = self.compute_gradients(pass, expected, loss)?;
+        return Sgd::new().step(&mut self.layers, &gradients, learning_rate);
+    }
+    /// Converts the network to another precision, e.g. to run gradient checks in `f64`.
+    pub fn cast<U: Float>(&self) -> Network<U> {
+        return Network { layers: self.layers.iter().map(Layer::cast).collect() };
     }
-    // pub fn backpropagate2(&mut self, result: &Vec<Matrix>, expected: Matrix, derivative_activation_function: &dyn Fn(f32) -> f32, learning_rate: f32) {
-    //     // Calculate the initial error
-    //     let mut errors = Matrix::matrix_subtraction(&expected, &result.last().unwrap()).unwrap();
-    //     for layer_index in (0..self.layers.len()).rev() {
-    //         let mut delta_weights =
-    //             Matrix::new_zeroed(self.layers[layer_index].weights.rows, self.layers[layer_index].weights.cols).unwrap();
-    //         let mut delta_biases =
-    //             Matrix::new_zeroed(self.layers[layer_index].biases.rows, 1).unwrap();
-    //
-    //         for i in 0..expected.cols {
-    //             // Apply the derivative of the activation function to the output of the current layer
-    //             let d_out_dnet = result[layer_index + 1].get_single_col(i).apply_function(derivative_activation_function);
-    //
-    //             // get the inputs of the current layer
-    //             let d_net_w = &result[layer_index].get_single_col(i);
-    //
-    //             // Calculate the weights to be subtracted
-    //             let weight_changes_without_inputs = Matrix::matrix_component_multiplication(&d_out_dnet, &errors).unwrap();
-    //             let temp = &Matrix::matrix_multiplication(&d_net_w, &weight_changes_without_inputs.transpose()).unwrap();
-    //             delta_weights = Matrix::matrix_addition(&delta_weights, &temp.transpose()).unwrap();
-    //             delta_biases = Matrix::matrix_addition(&delta_biases, &Matrix::matrix_component_multiplication(&errors.get_single_col(i), &d_out_dnet).unwrap()).unwrap()
-    //         }
-    //         delta_weights = Matrix::scalar_multiplication(&delta_weights, learning_rate / expected.cols as f32);
-    //
-    //         delta_biases = Matrix::scalar_multiplication(&delta_biases, learning_rate / expected.cols as f32);
-    //         if layer_index != 0 {
-    //             // Calculate the error of the layer below
-    //             errors = Matrix::matrix_multiplication(&self.layers[layer_index].weights.transpose(), &errors).unwrap();
-    //         }
-    //         self.layers.get_mut(layer_index).unwrap().weights = Matrix::matrix_subtraction(&self.layers.get(layer_index).unwrap().weights, &delta_weights).unwrap();
-    //         self.layers.get_mut(layer_index).unwrap().change_bias(delta_biases);
-    //     }
-    // }
 }
 
-impl Debug for Network {
+impl<T: Float> Debug for Network<T> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         for layer in self.layers.iter() {
             write!(f, "Layer: \n{:?}\n", layer)?
@@ -99,3 +199,24 @@ impl Debug for Network {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_network_needs_a_layer() {
+        assert!(matches!(Network::<f64>::new(&[3], Activation::Sigmoid), Err(NnError::EmptyDimension)));
+        assert!(matches!(Network::<f64>::from_layers(Vec::new()), Err(NnError::EmptyDimension)));
+        assert_eq!(Network::<f64>::new(&[3, 2], Activation::Sigmoid).unwrap().layers.len(), 1);
+    }
+
+    #[test]
+    fn every_layer_uses_the_given_initializers() {
+        let network = Network::<f64>::initialized(&[3, 4, 2], Activation::Relu, Initializer::Zeros, Initializer::Constant { value: 0.5 }, &mut rand::thread_rng()).unwrap();
+        assert_eq!(network.layers.len(), 2);
+        for layer in network.layers.iter() {
+            assert!(layer.weights.values().iter().all(|&x| x == 0.0));
+            assert!(layer.biases.values().iter().all(|&x| x == 0.5));
+        }
+    }
+}
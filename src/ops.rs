@@ -0,0 +1,247 @@
+//! Operator overloads for `Matrix`.
+//!
+//! The operators panic when the shapes of their operands do not fit together.
+//! Use `Matrix::matrix_addition`, `Matrix::matrix_subtraction` and
+//! `Matrix::matrix_multiplication` for the checked alternatives.
+
+use std::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use crate::error::NnError;
+use crate::float::Float;
+use crate::matrix::Matrix;
+
+/// Unwraps the result of a checked operation, panicking with the error message.
+fn expect<R>(result: Result<R, NnError>) -> R {
+    result.unwrap_or_else(|error| panic!("{}", error))
+}
+
+impl<T: Float> Index<(usize, usize)> for Matrix<T> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, (row, col): (usize, usize)) -> &T {
+        assert!(row < self.rows && col < self.cols, "Index ({}, {}) is out of bounds for a {}x{} matrix.", row, col, self.rows, self.cols);
+        &self.values[self.offset(row, col)]
+    }
+}
+
+impl<T: Float> IndexMut<(usize, usize)> for Matrix<T> {
+    #[inline]
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut T {
+        assert!(row < self.rows && col < self.cols, "Index ({}, {}) is out of bounds for a {}x{} matrix.", row, col, self.rows, self.cols);
+        let offset = self.offset(row, col);
+        &mut self.values[offset]
+    }
+}
+
+impl<T: Float> AddAssign<&Matrix<T>> for Matrix<T> {
+    fn add_assign(&mut self, rhs: &Matrix<T>) {
+        expect(self.matrix_addition_mut(rhs));
+    }
+}
+
+impl<T: Float> SubAssign<&Matrix<T>> for Matrix<T> {
+    fn sub_assign(&mut self, rhs: &Matrix<T>) {
+        expect(self.matrix_subtraction_mut(rhs));
+    }
+}
+
+impl<T: Float> MulAssign<&Matrix<T>> for Matrix<T> {
+    fn mul_assign(&mut self, rhs: &Matrix<T>) {
+        *self = &*self * rhs;
+    }
+}
+
+impl<T: Float> AddAssign<T> for Matrix<T> {
+    fn add_assign(&mut self, rhs: T) {
+        for x in self.values.iter_mut() {
+            *x += rhs;
+        }
+    }
+}
+
+impl<T: Float> SubAssign<T> for Matrix<T> {
+    fn sub_assign(&mut self, rhs: T) {
+        for x in self.values.iter_mut() {
+            *x -= rhs;
+        }
+    }
+}
+
+impl<T: Float> MulAssign<T> for Matrix<T> {
+    fn mul_assign(&mut self, rhs: T) {
+        for x in self.values.iter_mut() {
+            *x *= rhs;
+        }
+    }
+}
+
+impl<T: Float> DivAssign<T> for Matrix<T> {
+    fn div_assign(&mut self, rhs: T) {
+        for x in self.values.iter_mut() {
+            *x /= rhs;
+        }
+    }
+}
+
+impl<T: Float> Mul<&Matrix<T>> for &Matrix<T> {
+    type Output = Matrix<T>;
+
+    fn mul(self, rhs: &Matrix<T>) -> Matrix<T> {
+        expect(Matrix::matrix_multiplication(self, rhs))
+    }
+}
+
+impl<T: Float> Neg for Matrix<T> {
+    type Output = Matrix<T>;
+
+    fn neg(mut self) -> Matrix<T> {
+        for x in self.values.iter_mut() {
+            *x = -*x;
+        }
+        self
+    }
+}
+
+impl<T: Float> Neg for &Matrix<T> {
+    type Output = Matrix<T>;
+
+    fn neg(self) -> Matrix<T> {
+        -self.clone()
+    }
+}
+
+/// Implements the element-wise operator for every combination of owned and
+/// borrowed operands on top of the `*Assign` form, reusing an owned left buffer.
+macro_rules! elementwise_op {
+    ($op:ident, $method:ident, $assign:ident) => {
+        impl<T: Float> $op<&Matrix<T>> for Matrix<T> {
+            type Output = Matrix<T>;
+
+            fn $method(mut self, rhs: &Matrix<T>) -> Matrix<T> {
+                self.$assign(rhs);
+                self
+            }
+        }
+
+        impl<T: Float> $op<Matrix<T>> for Matrix<T> {
+            type Output = Matrix<T>;
+
+            fn $method(self, rhs: Matrix<T>) -> Matrix<T> {
+                self.$method(&rhs)
+            }
+        }
+
+        impl<T: Float> $op<&Matrix<T>> for &Matrix<T> {
+            type Output = Matrix<T>;
+
+            fn $method(self, rhs: &Matrix<T>) -> Matrix<T> {
+                self.clone().$method(rhs)
+            }
+        }
+
+        impl<T: Float> $op<Matrix<T>> for &Matrix<T> {
+            type Output = Matrix<T>;
+
+            fn $method(self, rhs: Matrix<T>) -> Matrix<T> {
+                self.clone().$method(&rhs)
+            }
+        }
+    };
+}
+
+elementwise_op!(Add, add, add_assign);
+elementwise_op!(Sub, sub, sub_assign);
+
+impl<T: Float> Mul<Matrix<T>> for Matrix<T> {
+    type Output = Matrix<T>;
+
+    fn mul(self, rhs: Matrix<T>) -> Matrix<T> {
+        &self * &rhs
+    }
+}
+
+impl<T: Float> Mul<&Matrix<T>> for Matrix<T> {
+    type Output = Matrix<T>;
+
+    fn mul(self, rhs: &Matrix<T>) -> Matrix<T> {
+        &self * rhs
+    }
+}
+
+impl<T: Float> Mul<Matrix<T>> for &Matrix<T> {
+    type Output = Matrix<T>;
+
+    fn mul(self, rhs: Matrix<T>) -> Matrix<T> {
+        self * &rhs
+    }
+}
+
+impl<T: Float> AddAssign<Matrix<T>> for Matrix<T> {
+    fn add_assign(&mut self, rhs: Matrix<T>) {
+        *self += &rhs;
+    }
+}
+
+impl<T: Float> SubAssign<Matrix<T>> for Matrix<T> {
+    fn sub_assign(&mut self, rhs: Matrix<T>) {
+        *self -= &rhs;
+    }
+}
+
+impl<T: Float> MulAssign<Matrix<T>> for Matrix<T> {
+    fn mul_assign(&mut self, rhs: Matrix<T>) {
+        *self *= &rhs;
+    }
+}
+
+/// Implements `matrix op scalar` for owned and borrowed matrices.
+macro_rules! scalar_op {
+    ($op:ident, $method:ident, $assign:ident) => {
+        impl<T: Float> $op<T> for Matrix<T> {
+            type Output = Matrix<T>;
+
+            fn $method(mut self, rhs: T) -> Matrix<T> {
+                self.$assign(rhs);
+                self
+            }
+        }
+
+        impl<T: Float> $op<T> for &Matrix<T> {
+            type Output = Matrix<T>;
+
+            fn $method(self, rhs: T) -> Matrix<T> {
+                self.clone().$method(rhs)
+            }
+        }
+    };
+}
+
+scalar_op!(Add, add, add_assign);
+scalar_op!(Sub, sub, sub_assign);
+scalar_op!(Mul, mul, mul_assign);
+scalar_op!(Div, div, div_assign);
+
+/// Implements `scalar * matrix`, which has to name the concrete float type.
+macro_rules! scalar_lhs_mul {
+    ($t:ty) => {
+        impl Mul<Matrix<$t>> for $t {
+            type Output = Matrix<$t>;
+
+            fn mul(self, rhs: Matrix<$t>) -> Matrix<$t> {
+                rhs * self
+            }
+        }
+
+        impl Mul<&Matrix<$t>> for $t {
+            type Output = Matrix<$t>;
+
+            fn mul(self, rhs: &Matrix<$t>) -> Matrix<$t> {
+                rhs * self
+            }
+        }
+    };
+}
+
+scalar_lhs_mul!(f32);
+scalar_lhs_mul!(f64);
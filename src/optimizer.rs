@@ -0,0 +1,370 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::NnError;
+use crate::float::Float;
+use crate::layer::Layer;
+use crate::matrix::Matrix;
+use crate::network::LayerGradients;
+
+/// Updates the parameters of a network from their gradients.
+///
+/// Optimizers keep their per-parameter state themselves and allocate it on the
+/// first step, so one instance belongs to one network. Every built-in optimizer
+/// is serializable, state included, so that training can be resumed.
+pub trait Optimizer<T: Float> {
+    fn name(&self) -> &'static str;
+    fn step(&mut self, layers: &mut [Layer<T>], gradients: &[LayerGradients<T>], learning_rate: T) -> Result<(), NnError>;
+}
+
+/// One state buffer for the weights and one for the biases of a layer.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct Slot<T: Float = f32> {
+    pub weights: Matrix<T>,
+    pub biases: Matrix<T>,
+}
+
+impl<T: Float> Slot<T> {
+    fn zeroed_like(gradients: &LayerGradients<T>) -> Result<Slot<T>, NnError> {
+        return Ok(Slot {
+            weights: Matrix::new_zeroed(gradients.weights.rows(), gradients.weights.cols())?,
+            biases: Matrix::new_zeroed(gradients.biases.rows(), gradients.biases.cols())?,
+        });
+    }
+}
+
+/// Allocates zeroed slots on the first step and checks that later steps see the same shapes.
+fn prepare_slots<T: Float>(slots: &mut Vec<Slot<T>>, layers: &[Layer<T>], gradients: &[LayerGradients<T>]) -> Result<(), NnError> {
+    if layers.len() != gradients.len() {
+        return Err(NnError::LengthMismatch { expected: layers.len(), found: gradients.len() });
+    }
+    for (layer, gradient) in layers.iter().zip(gradients) {
+        Matrix::check_same_shape("update", &layer.weights, &gradient.weights)?;
+        Matrix::check_same_shape("update", &layer.biases, &gradient.biases)?;
+    }
+    if slots.is_empty() {
+        for gradient in gradients {
+            slots.push(Slot::zeroed_like(gradient)?);
+        }
+    }
+    if slots.len() != gradients.len() {
+        return Err(NnError::LengthMismatch { expected: slots.len(), found: gradients.len() });
+    }
+    for (slot, gradient) in slots.iter().zip(gradients) {
+        Matrix::check_same_shape("update", &slot.weights, &gradient.weights)?;
+        Matrix::check_same_shape("update", &slot.biases, &gradient.biases)?;
+    }
+    return Ok(());
+}
+
+/// Calls `update(layer_index, is_weight, index, parameter, gradient)` for every
+/// weight and bias, where the first three address the matching entry of a slot.
+fn for_each_parameter<T: Float>(layers: &mut [Layer<T>], gradients: &[LayerGradients<T>], update: &mut dyn FnMut(usize, bool, usize, &mut T, T)) {
+    for (layer_index, (layer, gradient)) in layers.iter_mut().zip(gradients).enumerate() {
+        for (i, (parameter, &g)) in layer.weights.values_mut().iter_mut().zip(gradient.weights.values()).enumerate() {
+            update(layer_index, true, i, parameter, g);
+        }
+        for (i, (parameter, &g)) in layer.biases.values_mut().iter_mut().zip(gradient.biases.values()).enumerate() {
+            update(layer_index, false, i, parameter, g);
+        }
+    }
+}
+
+/// The matching entry of a slot buffer.
+fn entry<T: Float>(slots: &mut [Slot<T>], layer_index: usize, weights: bool, i: usize) -> &mut T {
+    let slot = &mut slots[layer_index];
+    let buffer = if weights { &mut slot.weights } else { &mut slot.biases };
+    &mut buffer.values_mut()[i]
+}
+
+/// Stochastic gradient descent, optionally with classical or Nesterov momentum.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct Sgd<T: Float = f32> {
+    pub momentum: f64,
+    pub nesterov: bool,
+    velocities: Vec<Slot<T>>,
+}
+
+impl<T: Float> Sgd<T> {
+    pub fn new() -> Sgd<T> {
+        Sgd { momentum: 0.0, nesterov: false, velocities: Vec::new() }
+    }
+    pub fn with_momentum(momentum: f64) -> Sgd<T> {
+        Sgd { momentum, nesterov: false, velocities: Vec::new() }
+    }
+    pub fn nesterov(momentum: f64) -> Sgd<T> {
+        Sgd { momentum, nesterov: true, velocities: Vec::new() }
+    }
+}
+
+impl<T: Float> Optimizer<T> for Sgd<T> {
+    fn name(&self) -> &'static str {
+        "sgd"
+    }
+    fn step(&mut self, layers: &mut [Layer<T>], gradients: &[LayerGradients<T>], learning_rate: T) -> Result<(), NnError> {
+        if self.momentum == 0.0 {
+            if layers.len() != gradients.len() {
+                return Err(NnError::LengthMismatch { expected: layers.len(), found: gradients.len() });
+            }
+            for (layer, gradient) in layers.iter_mut().zip(gradients) {
+                layer.weights.matrix_subtraction_mut(&(&gradient.weights * learning_rate))?;
+                layer.biases.matrix_subtraction_mut(&(&gradient.biases * learning_rate))?;
+            }
+            return Ok(());
+        }
+        prepare_slots(&mut self.velocities, layers, gradients)?;
+        let momentum = T::from_f64(self.momentum);
+        let nesterov = self.nesterov;
+        let velocities = &mut self.velocities;
+        for_each_parameter(layers, gradients, &mut |layer_index, weights, i, parameter, g| {
+            let velocity = entry(velocities, layer_index, weights, i);
+            *velocity = momentum * *velocity + g;
+            let direction = if nesterov { g + momentum * *velocity } else { *velocity };
+            *parameter -= learning_rate * direction;
+        });
+        return Ok(());
+    }
+}
+
+/// Adam, or AdamW when `decoupled_weight_decay` is set.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct Adam<T: Float = f32> {
+    pub beta1: f64,
+    pub beta2: f64,
+    pub epsilon: f64,
+    /// Decays the weights towards zero, independently of the gradient (AdamW).
+    pub decoupled_weight_decay: f64,
+    steps: u64,
+    first_moments: Vec<Slot<T>>,
+    second_moments: Vec<Slot<T>>,
+}
+
+impl<T: Float> Default for Adam<T> {
+    fn default() -> Self {
+        Adam::new()
+    }
+}
+
+impl<T: Float> Adam<T> {
+    pub fn new() -> Adam<T> {
+        Adam {
+            beta1: 0.9,
+            beta2: 0.999,
+            epsilon: 1e-8,
+            decoupled_weight_decay: 0.0,
+            steps: 0,
+            first_moments: Vec::new(),
+            second_moments: Vec::new(),
+        }
+    }
+    pub fn adamw(weight_decay: f64) -> Adam<T> {
+        Adam { decoupled_weight_decay: weight_decay, ..Adam::new() }
+    }
+}
+
+impl<T: Float> Optimizer<T> for Adam<T> {
+    fn name(&self) -> &'static str {
+        if self.decoupled_weight_decay > 0.0 { "adamw" } else { "adam" }
+    }
+    fn step(&mut self, layers: &mut [Layer<T>], gradients: &[LayerGradients<T>], learning_rate: T) -> Result<(), NnError> {
+        prepare_slots(&mut self.first_moments, layers, gradients)?;
+        prepare_slots(&mut self.second_moments, layers, gradients)?;
+        self.steps += 1;
+        let beta1 = T::from_f64(self.beta1);
+        let beta2 = T::from_f64(self.beta2);
+        let epsilon = T::from_f64(self.epsilon);
+        let weight_decay = T::from_f64(self.decoupled_weight_decay);
+        let correction1 = T::one() - T::from_f64(self.beta1.powf(self.steps as f64));
+        let correction2 = T::one() - T::from_f64(self.beta2.powf(self.steps as f64));
+        let first_moments = &mut self.first_moments;
+        let second_moments = &mut self.second_moments;
+        for_each_parameter(layers, gradients, &mut |layer_index, weights, i, parameter, g| {
+            let m = entry(first_moments, layer_index, weights, i);
+            *m = beta1 * *m + (T::one() - beta1) * g;
+            let m_hat = *m / correction1;
+            let v = entry(second_moments, layer_index, weights, i);
+            *v = beta2 * *v + (T::one() - beta2) * g * g;
+            let v_hat = *v / correction2;
+            if weights {
+                *parameter -= learning_rate * weight_decay * *parameter;
+            }
+            *parameter -= learning_rate * m_hat / (v_hat.sqrt() + epsilon);
+        });
+        return Ok(());
+    }
+}
+
+/// Scales every step by a running average of the squared gradients.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct RmsProp<T: Float = f32> {
+    pub decay: f64,
+    pub epsilon: f64,
+    mean_squares: Vec<Slot<T>>,
+}
+
+impl<T: Float> Default for RmsProp<T> {
+    fn default() -> Self {
+        RmsProp::new()
+    }
+}
+
+impl<T: Float> RmsProp<T> {
+    pub fn new() -> RmsProp<T> {
+        RmsProp { decay: 0.9, epsilon: 1e-8, mean_squares: Vec::new() }
+    }
+}
+
+impl<T: Float> Optimizer<T> for RmsProp<T> {
+    fn name(&self) -> &'static str {
+        "rmsprop"
+    }
+    fn step(&mut self, layers: &mut [Layer<T>], gradients: &[LayerGradients<T>], learning_rate: T) -> Result<(), NnError> {
+        prepare_slots(&mut self.mean_squares, layers, gradients)?;
+        let decay = T::from_f64(self.decay);
+        let epsilon = T::from_f64(self.epsilon);
+        let mean_squares = &mut self.mean_squares;
+        for_each_parameter(layers, gradients, &mut |layer_index, weights, i, parameter, g| {
+            let mean_square = entry(mean_squares, layer_index, weights, i);
+            *mean_square = decay * *mean_square + (T::one() - decay) * g * g;
+            *parameter -= learning_rate * g / (mean_square.sqrt() + epsilon);
+        });
+        return Ok(());
+    }
+}
+
+/// Scales every step by the sum of all squared gradients seen so far.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct Adagrad<T: Float = f32> {
+    pub epsilon: f64,
+    sums: Vec<Slot<T>>,
+}
+
+impl<T: Float> Default for Adagrad<T> {
+    fn default() -> Self {
+        Adagrad::new()
+    }
+}
+
+impl<T: Float> Adagrad<T> {
+    pub fn new() -> Adagrad<T> {
+        Adagrad { epsilon: 1e-8, sums: Vec::new() }
+    }
+}
+
+impl<T: Float> Optimizer<T> for Adagrad<T> {
+    fn name(&self) -> &'static str {
+        "adagrad"
+    }
+    fn step(&mut self, layers: &mut [Layer<T>], gradients: &[LayerGradients<T>], learning_rate: T) -> Result<(), NnError> {
+        prepare_slots(&mut self.sums, layers, gradients)?;
+        let epsilon = T::from_f64(self.epsilon);
+        let sums = &mut self.sums;
+        for_each_parameter(layers, gradients, &mut |layer_index, weights, i, parameter, g| {
+            let sum = entry(sums, layer_index, weights, i);
+            *sum += g * g;
+            *parameter -= learning_rate * g / (sum.sqrt() + epsilon);
+        });
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::Activation;
+    use serde::de::DeserializeOwned;
+
+    const LEARNING_RATE: f64 = 0.1;
+
+    /// A layer with two inputs and one output: weights `[1, -2]`, bias `[0.5]`.
+    fn layer() -> Layer<f64> {
+        Layer {
+            weights: Matrix::from_flat(2, 1, vec![1.0, -2.0]).unwrap(),
+            biases: Matrix::from_flat(1, 1, vec![0.5]).unwrap(),
+            activation: Activation::Sigmoid,
+        }
+    }
+
+    fn gradients(weights: [f64; 2], bias: f64) -> Vec<LayerGradients<f64>> {
+        vec![LayerGradients { weights: Matrix::from_flat(2, 1, weights.to_vec()).unwrap(), biases: Matrix::from_flat(1, 1, vec![bias]).unwrap() }]
+    }
+
+    /// Applies one step per gradient and checks the parameters against `[w1, w2, b]`.
+    fn check(optimizer: &mut dyn Optimizer<f64>, steps: &[&Vec<LayerGradients<f64>>], expected: [f64; 3]) {
+        let mut layers = vec![layer()];
+        for gradients in steps {
+            optimizer.step(&mut layers, gradients, LEARNING_RATE).unwrap();
+        }
+        let found = [layers[0].weights.values()[0], layers[0].weights.values()[1], layers[0].biases.values()[0]];
+        for (x, y) in found.iter().zip(&expected) {
+            assert!((x - y).abs() < 1e-6, "{} {:?} != {:?}", optimizer.name(), found, expected);
+        }
+    }
+
+    #[test]
+    fn sgd_follows_the_gradient_and_the_velocity() {
+        let g = gradients([0.5, -1.0], 2.0);
+        // p - 0.1 g
+        check(&mut Sgd::new(), &[&g], [0.95, -1.9, 0.3]);
+        // The velocity is g, then 1.9 g, so the steps add up to p - 0.29 g.
+        check(&mut Sgd::with_momentum(0.9), &[&g, &g], [0.855, -1.71, -0.08]);
+        // The directions are g + 0.9 v: 1.9 g, then 2.71 g, so p - 0.461 g.
+        check(&mut Sgd::nesterov(0.9), &[&g, &g], [0.7695, -1.539, -0.422]);
+    }
+
+    #[test]
+    fn adam_corrects_the_bias_of_its_moments() {
+        let g = gradients([0.5, -1.0], 2.0);
+        // After one step m̂ = g and v̂ = g², so every parameter moves by 0.1 against the sign of g.
+        check(&mut Adam::new(), &[&g], [0.9, -1.9, 0.4]);
+        // A zero gradient next gives m̂ = 0.09 g / 0.19 and v̂ = 0.000999 g² / 0.001999,
+        // a second step of 0.1 * 0.670058 against the sign of g.
+        let step = 0.1 + 0.0670058;
+        check(&mut Adam::new(), &[&g, &gradients([0.0, 0.0], 0.0)], [1.0 - step, -2.0 + step, 0.5 - step]);
+        // AdamW first shrinks the weights, not the bias, by 0.1 * 0.01 of themselves.
+        check(&mut Adam::adamw(0.01), &[&g], [0.999 - 0.1, -1.998 + 0.1, 0.4]);
+    }
+
+    #[test]
+    fn rmsprop_scales_by_the_mean_square() {
+        // The mean square is 0.1 g², so every parameter moves by 0.1 / √0.1.
+        let step = 0.316227766;
+        check(&mut RmsProp::new(), &[&gradients([0.5, -1.0], 2.0)], [1.0 - step, -2.0 + step, 0.5 - step]);
+    }
+
+    #[test]
+    fn adagrad_scales_by_the_sum_of_squares() {
+        // The sums are g², then 2 g², so the steps are 0.1 and 0.1 / √2.
+        let g = gradients([0.5, -1.0], 2.0);
+        let step = 0.1 + 0.0707106781;
+        check(&mut Adagrad::new(), &[&g, &g], [1.0 - step, -2.0 + step, 0.5 - step]);
+    }
+
+    /// Steps three times straight through, and checks that an optimizer saved
+    /// after the first step and loaded again takes the same two steps after it.
+    fn resumes_exactly<O: Optimizer<f64> + Serialize + DeserializeOwned>(mut optimizer: O) {
+        let steps = [gradients([0.5, -1.0], 2.0), gradients([-0.3, 0.2], 0.1), gradients([0.7, 0.0], -1.5)];
+        let mut straight = vec![layer()];
+        optimizer.step(&mut straight, &steps[0], LEARNING_RATE).unwrap();
+        let mut resumed = straight.clone();
+        let mut loaded: O = serde_json::from_str(&serde_json::to_string(&optimizer).unwrap()).unwrap();
+        for gradients in &steps[1..] {
+            optimizer.step(&mut straight, gradients, LEARNING_RATE).unwrap();
+            loaded.step(&mut resumed, gradients, LEARNING_RATE).unwrap();
+        }
+        assert_eq!(straight[0].weights.values(), resumed[0].weights.values());
+        assert_eq!(straight[0].biases.values(), resumed[0].biases.values());
+    }
+
+    #[test]
+    fn saved_state_resumes_exactly() {
+        resumes_exactly(Sgd::nesterov(0.9));
+        resumes_exactly(Adam::adamw(0.01));
+        resumes_exactly(RmsProp::new());
+        resumes_exactly(Adagrad::new());
+    }
+}
@@ -0,0 +1,240 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+/// How the learning rate changes over the course of training.
+///
+/// A schedule is queried with a position `t`, which the caller counts in
+/// optimizer steps or in epochs. `ReduceOnPlateau` additionally needs to see the
+/// monitored metric through `observe`. Schedules are plain data, so they can be
+/// configured from files and saved together with a checkpoint.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LrSchedule {
+    Constant { learning_rate: f64 },
+    /// Multiplies the learning rate by `gamma` every `step_size` positions.
+    StepDecay { initial: f64, gamma: f64, step_size: u64 },
+    /// Multiplies the learning rate by `gamma` at every position.
+    ExponentialDecay { initial: f64, gamma: f64 },
+    /// Cosine annealing from `max` to `min` that restarts after `period`
+    /// positions, with every following period `period_multiplier` times longer.
+    CosineWarmRestarts { max: f64, min: f64, period: u64, period_multiplier: u64 },
+    /// Ramps the learning rate up linearly over `warmup` positions, then hands
+    /// over to `after`, which starts counting from zero.
+    LinearWarmup { warmup: u64, after: Box<LrSchedule> },
+    /// Anneals from `max / div_factor` up to `max` during the first `pct_start`
+    /// of `total` positions and then down to `max / (div_factor * final_div_factor)`.
+    OneCycle { max: f64, total: u64, pct_start: f64, div_factor: f64, final_div_factor: f64 },
+    /// Multiplies the learning rate by `factor` once the observed metric has not
+    /// decreased by at least `min_delta` for more than `patience` observations.
+    ReduceOnPlateau {
+        current: f64,
+        factor: f64,
+        patience: u64,
+        min_delta: f64,
+        min: f64,
+        #[serde(default)]
+        best: Option<f64>,
+        #[serde(default)]
+        wait: u64,
+    },
+}
+
+/// Cosine interpolation from `start` at `progress = 0` to `end` at `progress = 1`.
+fn cosine(start: f64, end: f64, progress: f64) -> f64 {
+    end + (start - end) * (1.0 + (PI * progress).cos()) / 2.0
+}
+
+/// The position of `t` within its cycle of a warm-restart schedule, and the
+/// length of that cycle. Cycle `k` is `period * multiplier^k` long and starts
+/// at the sum of the ones before it, `period * (multiplier^k - 1) / (multiplier - 1)`.
+fn restart_cycle(t: u64, period: u64, multiplier: u64) -> (u64, u64) {
+    if multiplier == 1 {
+        return (t % period, period);
+    }
+    let start = |k: u32| multiplier.checked_pow(k).map_or(u64::MAX, |power| period.saturating_mul((power - 1) / (multiplier - 1)));
+    let mut k = (t as f64 * (multiplier - 1) as f64 / period as f64 + 1.0).log(multiplier as f64).floor() as u32;
+    // The logarithm may round to the neighbouring cycle right at a restart.
+    if start(k) > t {
+        k -= 1;
+    } else if start(k + 1) <= t {
+        k += 1;
+    }
+    return (t - start(k), period.saturating_mul(multiplier.saturating_pow(k)));
+}
+
+impl LrSchedule {
+    pub fn constant(learning_rate: f64) -> LrSchedule {
+        LrSchedule::Constant { learning_rate }
+    }
+    pub fn step_decay(initial: f64, gamma: f64, step_size: u64) -> LrSchedule {
+        LrSchedule::StepDecay { initial, gamma, step_size }
+    }
+    pub fn exponential_decay(initial: f64, gamma: f64) -> LrSchedule {
+        LrSchedule::ExponentialDecay { initial, gamma }
+    }
+    pub fn cosine_warm_restarts(max: f64, min: f64, period: u64, period_multiplier: u64) -> LrSchedule {
+        LrSchedule::CosineWarmRestarts { max, min, period, period_multiplier }
+    }
+    pub fn linear_warmup(warmup: u64, after: LrSchedule) -> LrSchedule {
+        LrSchedule::LinearWarmup { warmup, after: Box::new(after) }
+    }
+    /// A one-cycle schedule with the usual defaults of 30% warmup, a start at
+    /// `max / 25` and an end at `max / 25e4`.
+    pub fn one_cycle(max: f64, total: u64) -> LrSchedule {
+        LrSchedule::OneCycle { max, total, pct_start: 0.3, div_factor: 25.0, final_div_factor: 1e4 }
+    }
+    pub fn reduce_on_plateau(initial: f64, factor: f64, patience: u64, min_delta: f64, min: f64) -> LrSchedule {
+        LrSchedule::ReduceOnPlateau { current: initial, factor, patience, min_delta, min, best: None, wait: 0 }
+    }
+
+    /// The learning rate at position `t`.
+    pub fn learning_rate(&self, t: u64) -> f64 {
+        match self {
+            LrSchedule::Constant { learning_rate } => *learning_rate,
+            LrSchedule::StepDecay { initial, gamma, step_size } => {
+                initial * gamma.powf((t / (*step_size).max(1)) as f64)
+            }
+            LrSchedule::ExponentialDecay { initial, gamma } => initial * gamma.powf(t as f64),
+            LrSchedule::CosineWarmRestarts { max, min, period, period_multiplier } => {
+                let (position, length) = restart_cycle(t, (*period).max(1), (*period_multiplier).max(1));
+                cosine(*max, *min, position as f64 / length as f64)
+            }
+            LrSchedule::LinearWarmup { warmup, after } => {
+                if t < *warmup {
+                    after.learning_rate(0) * (t + 1) as f64 / *warmup as f64
+                } else {
+                    after.learning_rate(t - warmup)
+                }
+            }
+            LrSchedule::OneCycle { max, total, pct_start, div_factor, final_div_factor } => {
+                let initial = max / div_factor;
+                let last = initial / final_div_factor;
+                let rising = ((*total as f64 * pct_start) as u64).max(1);
+                let falling = total.saturating_sub(rising).max(1);
+                if t < rising {
+                    cosine(initial, *max, t as f64 / rising as f64)
+                } else if t - rising < falling {
+                    cosine(*max, last, (t - rising) as f64 / falling as f64)
+                } else {
+                    last
+                }
+            }
+            LrSchedule::ReduceOnPlateau { current, .. } => *current,
+        }
+    }
+
+    /// Reports the monitored metric, where lower is better. Only
+    /// `ReduceOnPlateau` reacts to it; every other schedule ignores it.
+    pub fn observe(&mut self, metric: f64) {
+        match self {
+            LrSchedule::ReduceOnPlateau { current, factor, patience, min_delta, min, best, wait } => {
+                if best.is_none_or(|best| metric < best - *min_delta) {
+                    *best = Some(metric);
+                    *wait = 0;
+                } else {
+                    *wait += 1;
+                    if *wait > *patience {
+                        *current = (*current * *factor).max(*min);
+                        *wait = 0;
+                    }
+                }
+            }
+            LrSchedule::LinearWarmup { after, .. } => after.observe(metric),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(found: f64, expected: f64) {
+        assert!((found - expected).abs() < 1e-12, "{} != {}", found, expected);
+    }
+
+    #[test]
+    fn decays_step_at_their_boundaries() {
+        assert_close(LrSchedule::constant(0.3).learning_rate(1_000_000), 0.3);
+        let step = LrSchedule::step_decay(1.0, 0.5, 3);
+        assert_close(step.learning_rate(2), 1.0);
+        assert_close(step.learning_rate(3), 0.5);
+        assert_close(step.learning_rate(6), 0.25);
+        let exponential = LrSchedule::exponential_decay(2.0, 0.5);
+        assert_close(exponential.learning_rate(0), 2.0);
+        assert_close(exponential.learning_rate(3), 0.25);
+    }
+
+    #[test]
+    fn warm_restarts_start_over_at_the_maximum() {
+        // Cycles of 4, 8 and 16 positions start at 0, 4, 12 and 28.
+        let growing = LrSchedule::cosine_warm_restarts(1.0, 0.0, 4, 2);
+        assert_close(growing.learning_rate(3), (1.0 + (0.75 * PI).cos()) / 2.0);
+        for &restart in &[0, 4, 12, 28] {
+            assert_close(growing.learning_rate(restart), 1.0);
+        }
+        assert_close(growing.learning_rate(8), 0.5);
+        assert_close(growing.learning_rate(20), 0.5);
+        let fixed = LrSchedule::cosine_warm_restarts(1.0, 0.0, 4, 1);
+        assert_close(fixed.learning_rate(8), 1.0);
+        assert_close(fixed.learning_rate(10), 0.5);
+    }
+
+    #[test]
+    fn restart_cycles_match_walking_through_them() {
+        for &(period, multiplier) in &[(1, 1), (5, 1), (1, 2), (3, 2), (2, 3), (7, 10)] {
+            let (mut position, mut length) = (0, period);
+            for t in 0..5000 {
+                assert_eq!(restart_cycle(t, period, multiplier), (position, length), "t = {}", t);
+                position += 1;
+                if position == length {
+                    position = 0;
+                    length *= multiplier;
+                }
+            }
+        }
+        assert_eq!(restart_cycle(u64::MAX - 1, 1, 2), ((1 << 63) - 1, 1 << 63));
+    }
+
+    #[test]
+    fn warmup_hands_over_at_its_end() {
+        let warmup = LrSchedule::linear_warmup(4, LrSchedule::step_decay(1.0, 0.5, 2));
+        assert_close(warmup.learning_rate(0), 0.25);
+        assert_close(warmup.learning_rate(3), 1.0);
+        assert_close(warmup.learning_rate(4), 1.0);
+        assert_close(warmup.learning_rate(6), 0.5);
+    }
+
+    #[test]
+    fn one_cycle_peaks_after_its_warmup() {
+        let one_cycle = LrSchedule::one_cycle(1.0, 100);
+        assert_close(one_cycle.learning_rate(0), 0.04);
+        assert!(one_cycle.learning_rate(29) < 1.0);
+        assert_close(one_cycle.learning_rate(30), 1.0);
+        assert!(one_cycle.learning_rate(31) < 1.0);
+        assert_close(one_cycle.learning_rate(100), 4e-6);
+        assert_close(one_cycle.learning_rate(1000), 4e-6);
+    }
+
+    #[test]
+    fn plateaus_reduce_after_the_patience_runs_out() {
+        let mut plateau = LrSchedule::reduce_on_plateau(1.0, 0.5, 2, 0.1, 0.3);
+        plateau.observe(1.0);
+        // Not better by more than min_delta, but still within the patience.
+        plateau.observe(0.95);
+        plateau.observe(1.0);
+        assert_close(plateau.learning_rate(0), 1.0);
+        plateau.observe(1.0);
+        assert_close(plateau.learning_rate(0), 0.5);
+        // An improvement resets the wait.
+        plateau.observe(0.5);
+        plateau.observe(0.5);
+        plateau.observe(0.5);
+        assert_close(plateau.learning_rate(0), 0.5);
+        for _ in 0..6 {
+            plateau.observe(0.5);
+        }
+        assert_close(plateau.learning_rate(0), 0.3);
+    }
+}
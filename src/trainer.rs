@@ -0,0 +1,583 @@
+use std::fs;
+use std::path::Path;
+
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::dataset::{Batch, DataLoader, Dataset};
+use crate::error::NnError;
+use crate::float::Float;
+use crate::loss::Loss;
+use crate::matrix::Matrix;
+use crate::model::TrainingConfig;
+use crate::network::Network;
+use crate::optimizer::Optimizer;
+use crate::schedule::LrSchedule;
+use crate::checkpoint::{self, CheckpointPolicy, TrainingCheckpoint, CHECKPOINT_VERSION};
+use crate::callback::{BatchMetrics, Callback, EarlyStopping, LrScheduler, ScheduleUnit, TrainingState};
+
+/// The loss and accuracy of one epoch.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EpochMetrics {
+    pub epoch: usize,
+    pub learning_rate: f64,
+    pub train_loss: f64,
+    pub train_accuracy: f64,
+    pub validation_loss: Option<f64>,
+    pub validation_accuracy: Option<f64>,
+}
+
+/// The metrics of every epoch of a training run.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct History {
+    pub epochs: Vec<EpochMetrics>,
+    /// The epoch whose weights the network ended up with, if early stopping restored them.
+    #[serde(default)]
+    pub best_epoch: Option<usize>,
+}
+
+/// A per-epoch metric that training can be monitored on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    TrainLoss,
+    TrainAccuracy,
+    ValidationLoss,
+    ValidationAccuracy,
+}
+
+impl Metric {
+    /// The value of the metric in an epoch. Validation metrics fall back to
+    /// their training counterpart when there is no validation set.
+    pub fn value(&self, metrics: &EpochMetrics) -> f64 {
+        match self {
+            Metric::TrainLoss => metrics.train_loss,
+            Metric::TrainAccuracy => metrics.train_accuracy,
+            Metric::ValidationLoss => metrics.validation_loss.unwrap_or(metrics.train_loss),
+            Metric::ValidationAccuracy => metrics.validation_accuracy.unwrap_or(metrics.train_accuracy),
+        }
+    }
+    /// Losses improve by decreasing, accuracies by increasing.
+    pub fn is_improvement(&self, value: f64, best: f64, min_delta: f64) -> bool {
+        match self {
+            Metric::TrainLoss | Metric::ValidationLoss => value < best - min_delta,
+            Metric::TrainAccuracy | Metric::ValidationAccuracy => value > best + min_delta,
+        }
+    }
+}
+
+/// The batches of one epoch, as handed to the epoch loop.
+type BatchIter<'a, T> = Box<dyn Iterator<Item = Result<Batch<T>, NnError>> + 'a>;
+
+/// Where a resumed run picks up.
+struct Progress {
+    epoch: usize,
+    step: u64,
+    learning_rate: f64,
+    history: History,
+}
+
+/// Drives `Network` training over a data set with one sample per row, in
+/// shuffled mini-batches, for a fixed number of epochs.
+///
+/// The optimizer has to be serializable so that checkpoints can capture its state.
+pub struct Trainer<T: Float, O: Optimizer<T>> {
+    pub epochs: usize,
+    pub batch_size: usize,
+    /// Draws a new order of the training samples at the start of every epoch.
+    pub shuffle: bool,
+    /// The learning rate until a callback changes it.
+    pub learning_rate: f64,
+    pub loss: Box<dyn Loss<T>>,
+    pub optimizer: O,
+    pub callbacks: Vec<Box<dyn Callback<T>>>,
+    /// Shuffles the samples. Its state is part of every checkpoint. Seed it,
+    /// or hand over the generator that initialized the network, to make runs
+    /// repeatable.
+    pub rng: ChaCha12Rng,
+    /// A run that diverges stops with `NnError::Diverged` at its next
+    /// checkpoint instead of writing one that could not be resumed.
+    pub checkpoints: Option<CheckpointPolicy>,
+    resume: Option<Progress>,
+}
+
+impl<T: Float, O: Optimizer<T> + Serialize + DeserializeOwned> Trainer<T, O> {
+    /// A trainer that runs 10 epochs of shuffled batches of 32 samples at a constant learning rate of 0.001.
+    pub fn new(loss: Box<dyn Loss<T>>, optimizer: O) -> Trainer<T, O> {
+        Trainer {
+            epochs: 10,
+            batch_size: 32,
+            shuffle: true,
+            learning_rate: 0.001,
+            loss,
+            optimizer,
+            callbacks: Vec::new(),
+            rng: ChaCha12Rng::from_entropy(),
+            checkpoints: None,
+            resume: None,
+        }
+    }
+    pub fn with_epochs(mut self, epochs: usize) -> Self {
+        self.epochs = epochs;
+        self
+    }
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+    pub fn with_shuffle(mut self, shuffle: bool) -> Self {
+        self.shuffle = shuffle;
+        self
+    }
+    pub fn with_learning_rate(mut self, learning_rate: f64) -> Self {
+        self.learning_rate = learning_rate;
+        self
+    }
+    pub fn with_callback<C: Callback<T> + 'static>(mut self, callback: C) -> Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = ChaCha12Rng::seed_from_u64(seed);
+        self
+    }
+    pub fn with_rng(mut self, rng: ChaCha12Rng) -> Self {
+        self.rng = rng;
+        self
+    }
+    pub fn with_checkpoints(mut self, policy: CheckpointPolicy) -> Self {
+        self.checkpoints = Some(policy);
+        self
+    }
+    /// Shorthand for adding an `LrScheduler` callback.
+    pub fn with_schedule(self, schedule: LrSchedule, unit: ScheduleUnit) -> Self {
+        self.with_callback(LrScheduler::new(schedule, unit))
+    }
+    /// Shorthand for adding an `EarlyStopping` callback.
+    pub fn with_early_stopping(self, early_stopping: EarlyStopping<T>) -> Self {
+        self.with_callback(early_stopping)
+    }
+
+    /// The settings of this trainer, to be saved along with the trained network.
+    pub fn config(&self) -> TrainingConfig {
+        TrainingConfig {
+            epochs: self.epochs,
+            batch_size: self.batch_size,
+            learning_rate: self.learning_rate,
+            loss: self.loss.name().to_string(),
+            optimizer: self.optimizer.name().to_string(),
+        }
+    }
+
+    /// Trains the network on `inputs` and `targets` and evaluates it on the
+    /// validation set, if there is one, after every epoch.
+    pub fn fit(&mut self, network: &mut Network<T>, inputs: &Matrix<T>, targets: &Matrix<T>, validation: Option<(&Matrix<T>, &Matrix<T>)>) -> Result<History, NnError> {
+        if inputs.rows() != targets.rows() {
+            return Err(NnError::ShapeMismatch { op: "train on", left: inputs.shape(), right: targets.shape() });
+        }
+        if self.batch_size == 0 {
+            return Err(NnError::EmptyDimension);
+        }
+        let (batch_size, shuffle) = (self.batch_size, self.shuffle);
+        let epoch_batches = |rng: &mut ChaCha12Rng| -> Result<BatchIter<'_, T>, NnError> {
+            let mut order: Vec<usize> = (0..inputs.rows()).collect();
+            if shuffle {
+                order.shuffle(rng);
+            }
+            let batches: Vec<Vec<usize>> = order.chunks(batch_size).map(<[usize]>::to_vec).collect();
+            return Ok(Box::new(batches.into_iter().map(move |indices| Ok((inputs.select_rows(&indices)?, targets.select_rows(&indices)?)))));
+        };
+        let validate = |trainer: &Self, network: &Network<T>| -> Result<Option<(f64, f64)>, NnError> {
+            match validation {
+                Some((validation_inputs, validation_targets)) => Ok(Some(trainer.evaluate(network, validation_inputs, validation_targets)?)),
+                None => Ok(None),
+            }
+        };
+        return self.run(network, epoch_batches, validate);
+    }
+
+    /// Like `fit`, but draws the batches from data loaders. The training
+    /// loader decides the batch size, the shuffling and whether the last
+    /// batch is dropped; the trainer's `batch_size` and `shuffle` are not used.
+    /// The trainer's generator shuffles the loader, so checkpoints resume it
+    /// exactly as they do `fit`.
+    pub fn fit_loader<D: Dataset<T> + 'static, V: Dataset<T> + 'static>(&mut self, network: &mut Network<T>, train: &DataLoader<T, D>, validation: Option<&DataLoader<T, V>>) -> Result<History, NnError> {
+        let epoch_batches = |rng: &mut ChaCha12Rng| -> Result<BatchIter<'_, T>, NnError> {
+            return Ok(Box::new(train.epoch(rng)?));
+        };
+        let validate = |trainer: &Self, network: &Network<T>| -> Result<Option<(f64, f64)>, NnError> {
+            match validation {
+                // The order of the validation samples does not change the scores,
+                // so it is not drawn from the trainer's generator.
+                Some(loader) => Ok(Some(trainer.score(network, loader.epoch(&mut ChaCha12Rng::seed_from_u64(0))?)?)),
+                None => Ok(None),
+            }
+        };
+        return self.run(network, epoch_batches, validate);
+    }
+
+    /// The epoch loop behind `fit` and `fit_loader`. `epoch_batches` hands out
+    /// the batches of one epoch, `validate` scores the network after it.
+    fn run<'a, B, E>(&mut self, network: &mut Network<T>, mut epoch_batches: B, mut validate: E) -> Result<History, NnError>
+    where
+        B: FnMut(&mut ChaCha12Rng) -> Result<BatchIter<'a, T>, NnError>,
+        E: FnMut(&Self, &Network<T>) -> Result<Option<(f64, f64)>, NnError>,
+    {
+        let progress = self.resume.take().unwrap_or(Progress { epoch: 0, step: 0, learning_rate: self.learning_rate, history: History::default() });
+        let mut history = progress.history;
+        let mut state = TrainingState { epoch: progress.epoch, step: progress.step, learning_rate: progress.learning_rate, stop: false };
+        if let Some(policy) = &self.checkpoints {
+            fs::create_dir_all(&policy.directory)?;
+        }
+        for epoch in progress.epoch..self.epochs {
+            state.epoch = epoch;
+            for callback in &mut self.callbacks {
+                callback.on_epoch_start(&mut state, network)?;
+            }
+            let mut loss_sum = 0.0;
+            let mut correct = 0;
+            let mut seen = 0;
+            for (batch, pair) in epoch_batches(&mut self.rng)?.enumerate() {
+                let (batch_inputs, batch_targets) = pair?;
+                let size = batch_inputs.rows();
+                for callback in &mut self.callbacks {
+                    callback.on_batch_start(&mut state, network)?;
+                }
+                let pass = network.train_batch(batch_inputs, &batch_targets, self.loss.as_ref(), &mut self.optimizer, T::from_f64(state.learning_rate))?;
+                let loss = self.loss.value(&batch_targets, pass.output())?.to_f64();
+                let batch_correct = count_correct(pass.output(), &batch_targets);
+                loss_sum += loss * size as f64;
+                correct += batch_correct;
+                seen += size;
+                state.step += 1;
+                let metrics = BatchMetrics { epoch, batch, size, loss, accuracy: batch_correct as f64 / size as f64 };
+                for callback in &mut self.callbacks {
+                    callback.on_batch_end(&metrics, &mut state, network)?;
+                }
+            }
+            if seen == 0 {
+                return Err(NnError::EmptyDimension);
+            }
+            let (validation_loss, validation_accuracy) = match validate(self, network)? {
+                Some((loss, accuracy)) => (Some(loss), Some(accuracy)),
+                None => (None, None),
+            };
+            let metrics = EpochMetrics {
+                epoch,
+                learning_rate: state.learning_rate,
+                train_loss: loss_sum / seen as f64,
+                train_accuracy: correct as f64 / seen as f64,
+                validation_loss,
+                validation_accuracy,
+            };
+            for callback in &mut self.callbacks {
+                callback.on_epoch_end(&metrics, &mut state, network)?;
+            }
+            history.epochs.push(metrics);
+            if let Some(policy) = &self.checkpoints {
+                if policy.interval > 0 && (epoch + 1).is_multiple_of(policy.interval) {
+                    self.checkpoint(&state, network, &history)?.save(policy.path(epoch))?;
+                    checkpoint::rotate(&policy.directory, policy.keep_last)?;
+                }
+            }
+            if state.stop {
+                break;
+            }
+        }
+        for callback in &mut self.callbacks {
+            callback.on_train_end(&mut history, network)?;
+        }
+        return Ok(history);
+    }
+
+    /// Captures a run after the epoch in `state` has ended.
+    fn checkpoint(&self, state: &TrainingState, network: &Network<T>, history: &History) -> Result<TrainingCheckpoint<T, &O>, NnError> {
+        return Ok(TrainingCheckpoint {
+            version: CHECKPOINT_VERSION,
+            epoch: state.epoch + 1,
+            step: state.step,
+            learning_rate: state.learning_rate,
+            network: network.clone(),
+            optimizer: &self.optimizer,
+            rng: self.rng.clone(),
+            callbacks: self.callbacks.iter().map(|callback| callback.state()).collect::<Result<_, _>>()?,
+            history: history.clone(),
+        });
+    }
+
+    /// Loads a checkpoint into the network, the optimizer, the random number
+    /// generator and the callbacks, so that the next `fit` continues the run
+    /// it was taken from. The trainer has to have the same callbacks, in the
+    /// same order, as the one that wrote the checkpoint.
+    pub fn resume<P: AsRef<Path>>(&mut self, network: &mut Network<T>, path: P) -> Result<(), NnError> {
+        let checkpoint: TrainingCheckpoint<T, O> = TrainingCheckpoint::load(path)?;
+        if checkpoint.callbacks.len() != self.callbacks.len() {
+            return Err(NnError::LengthMismatch { expected: self.callbacks.len(), found: checkpoint.callbacks.len() });
+        }
+        for (callback, state) in self.callbacks.iter_mut().zip(checkpoint.callbacks) {
+            if let Some(state) = state {
+                callback.restore_state(state)?;
+            }
+        }
+        *network = checkpoint.network;
+        self.optimizer = checkpoint.optimizer;
+        self.rng = checkpoint.rng;
+        self.resume = Some(Progress {
+            epoch: checkpoint.epoch,
+            step: checkpoint.step,
+            learning_rate: checkpoint.learning_rate,
+            history: checkpoint.history,
+        });
+        return Ok(());
+    }
+
+    /// The mean loss and the accuracy of the network on a data set, computed batch by batch.
+    pub fn evaluate(&self, network: &Network<T>, inputs: &Matrix<T>, targets: &Matrix<T>) -> Result<(f64, f64), NnError> {
+        if inputs.rows() != targets.rows() {
+            return Err(NnError::ShapeMismatch { op: "evaluate on", left: inputs.shape(), right: targets.shape() });
+        }
+        let order: Vec<usize> = (0..inputs.rows()).collect();
+        let batches = order.chunks(self.batch_size.max(1)).map(|batch| Ok((inputs.select_rows(batch)?, targets.select_rows(batch)?)));
+        return self.score(network, batches);
+    }
+
+    /// The mean loss and the accuracy of the network over some batches.
+    fn score<I: Iterator<Item = Result<Batch<T>, NnError>>>(&self, network: &Network<T>, batches: I) -> Result<(f64, f64), NnError> {
+        let mut loss_sum = 0.0;
+        let mut correct = 0;
+        let mut seen = 0;
+        for pair in batches {
+            let (batch_inputs, batch_targets) = pair?;
+            let size = batch_inputs.rows();
+            let outputs = network.feedforward(batch_inputs)?;
+            let output = outputs.last().unwrap();
+            loss_sum += self.loss.value(&batch_targets, output)?.to_f64() * size as f64;
+            correct += count_correct(output, &batch_targets);
+            seen += size;
+        }
+        if seen == 0 {
+            return Err(NnError::EmptyDimension);
+        }
+        return Ok((loss_sum / seen as f64, correct as f64 / seen as f64));
+    }
+}
+
+/// The number of rows whose prediction matches the target. With one output per
+/// sample, predictions and targets are split at 0.5, otherwise the largest
+/// output has to be at the position of the largest target.
+fn count_correct<T: Float>(output: &Matrix<T>, targets: &Matrix<T>) -> usize {
+    if output.cols() == 1 {
+        let half = T::from_f64(0.5);
+        return output.values().iter().zip(targets.values()).filter(|(&p, &y)| (p >= half) == (y >= half)).count();
+    }
+    return output.argmax_rows().iter().zip(targets.argmax_rows()).filter(|(p, y)| **p == *y).count();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::Activation;
+    use crate::dataset::InMemoryDataset;
+    use crate::layer::Layer;
+    use crate::loss::CategoricalCrossEntropy;
+    use crate::optimizer::Adam;
+
+    /// Two noisy clusters with one-hot labels.
+    fn data() -> (Matrix<f64>, Matrix<f64>) {
+        let noise = |i: usize| ((i * 37) % 11) as f64 / 20.0 - 0.25;
+        let inputs = Matrix::from_flat(60, 2, (0..120).map(|i| noise(i) + if i / 2 % 2 == 0 { 1.0 } else { -1.0 }).collect()).unwrap();
+        let targets = Matrix::from_flat(60, 2, (0..60).flat_map(|i| if i % 2 == 0 { [1.0, 0.0] } else { [0.0, 1.0] }).collect()).unwrap();
+        return (inputs, targets);
+    }
+
+    /// A 2-5-2 network with fixed weights, so that every run starts the same.
+    fn network() -> Network<f64> {
+        let weights = |rows: usize, cols: usize| Matrix::from_flat(rows, cols, (0..rows * cols).map(|i| ((i * 7) % 5) as f64 / 4.0 - 0.5).collect()).unwrap();
+        return Network::from_layers(vec![
+            Layer { weights: weights(2, 5), biases: Matrix::new_zeroed(1, 5).unwrap(), activation: Activation::Tanh },
+            Layer { weights: weights(5, 2), biases: Matrix::new_zeroed(1, 2).unwrap(), activation: Activation::Softmax },
+        ]).unwrap();
+    }
+
+    /// The other class for every sample, so the validation loss rises as the network learns.
+    fn flipped(targets: &Matrix<f64>) -> Matrix<f64> {
+        return Matrix::from_flat(targets.rows(), 2, targets.values().iter().map(|y| 1.0 - y).collect()).unwrap();
+    }
+
+    fn assert_same_weights(left: &Network<f64>, right: &Network<f64>) {
+        for (a, b) in left.layers().iter().zip(right.layers()) {
+            assert_eq!(a.weights.values(), b.weights.values());
+            assert_eq!(a.biases.values(), b.biases.values());
+        }
+    }
+
+    fn checkpointed(epochs: usize, directory: &Path) -> Trainer<f64, Adam<f64>> {
+        return Trainer::new(Box::new(CategoricalCrossEntropy), Adam::new())
+            .with_epochs(epochs)
+            .with_batch_size(8)
+            .with_seed(3)
+            .with_schedule(LrSchedule::step_decay(0.05, 0.5, 10), ScheduleUnit::Step)
+            .with_early_stopping(EarlyStopping::new(Metric::ValidationLoss, 100, 0.0))
+            .with_checkpoints(CheckpointPolicy::new(directory, 1, 0));
+    }
+
+    #[test]
+    fn early_stopping_restores_the_best_epoch() {
+        let (inputs, targets) = data();
+        let wrong = flipped(&targets);
+        let initial = network();
+        let trainer = |epochs: usize| {
+            Trainer::new(Box::new(CategoricalCrossEntropy), Adam::new()).with_epochs(epochs).with_batch_size(8).with_shuffle(false)
+        };
+
+        let mut stopped = initial.clone();
+        let history = trainer(50)
+            .with_early_stopping(EarlyStopping::new(Metric::ValidationLoss, 3, 0.0))
+            .fit(&mut stopped, &inputs, &targets, Some((&inputs, &wrong)))
+            .unwrap();
+        let best = history.best_epoch.unwrap();
+        assert_eq!(history.epochs.len(), best + 1 + 3);
+        let best_loss = history.epochs[best].validation_loss.unwrap();
+        assert!(history.epochs.iter().all(|epoch| epoch.validation_loss.unwrap() >= best_loss));
+
+        // Without shuffling, training only up to the best epoch gives the weights it had.
+        let mut up_to_best = initial.clone();
+        trainer(best + 1).fit(&mut up_to_best, &inputs, &targets, None).unwrap();
+        assert_same_weights(&stopped, &up_to_best);
+    }
+
+    #[test]
+    fn the_callbacks_lower_the_rate_and_stop_the_run() {
+        let (inputs, targets) = data();
+        let wrong = flipped(&targets);
+        let history = Trainer::new(Box::new(CategoricalCrossEntropy), Adam::new())
+            .with_epochs(50)
+            .with_batch_size(8)
+            .with_shuffle(false)
+            .with_schedule(LrSchedule::reduce_on_plateau(0.01, 0.5, 0, 0.0, 0.0), ScheduleUnit::Epoch)
+            .with_early_stopping(EarlyStopping::new(Metric::ValidationLoss, 4, 0.0))
+            .fit(&mut network(), &inputs, &targets, Some((&inputs, &wrong)))
+            .unwrap();
+
+        let best = history.best_epoch.unwrap();
+        assert_eq!(history.epochs.len(), best + 1 + 4);
+        // Every epoch without improvement halves the rate of the one after it.
+        let rates: Vec<f64> = history.epochs[best + 1..].iter().map(|epoch| epoch.learning_rate).collect();
+        assert_eq!(rates, vec![0.01, 0.005, 0.0025, 0.00125]);
+    }
+
+    #[test]
+    fn resuming_a_checkpoint_continues_the_run_exactly() {
+        let directory = std::env::temp_dir().join(format!("trainer-test-{}", std::process::id()));
+        let (inputs, targets) = data();
+        let validation = Some((&inputs, &targets));
+
+        let mut uninterrupted = network();
+        let expected = checkpointed(6, &directory.join("full")).fit(&mut uninterrupted, &inputs, &targets, validation).unwrap();
+
+        // Stop after three epochs, then pick the run up with a fresh trainer and network.
+        let mut stopped = network();
+        checkpointed(3, &directory.join("stopped")).fit(&mut stopped, &inputs, &targets, validation).unwrap();
+        let path = checkpoint::latest(directory.join("stopped")).unwrap().unwrap();
+        let mut resumed = network();
+        let mut resuming = checkpointed(6, &directory.join("stopped"));
+        resuming.resume(&mut resumed, &path).unwrap();
+        let history = resuming.fit(&mut resumed, &inputs, &targets, validation).unwrap();
+
+        assert_eq!(history, expected);
+        assert_same_weights(&resumed, &uninterrupted);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    /// Trains a network initialized from `network_seed` for three epochs, shuffled from `trainer_seed`.
+    fn seeded_run(network_seed: u64, trainer_seed: u64) -> (Network<f64>, History) {
+        let (inputs, targets) = data();
+        let mut network = Network::new_with_rng(&[2, 5, 2], Activation::Tanh, &mut ChaCha12Rng::seed_from_u64(network_seed)).unwrap();
+        let history = Trainer::new(Box::new(CategoricalCrossEntropy), Adam::new())
+            .with_epochs(3)
+            .with_batch_size(8)
+            .with_seed(trainer_seed)
+            .fit(&mut network, &inputs, &targets, None)
+            .unwrap();
+        return (network, history);
+    }
+
+    fn weights(network: &Network<f64>) -> Vec<f64> {
+        return network.layers().iter().flat_map(|layer| layer.weights.values().iter().chain(layer.biases.values()).copied()).collect();
+    }
+
+    #[test]
+    fn the_same_seeds_give_the_same_run() {
+        let (network, history) = seeded_run(2, 3);
+        let (again, again_history) = seeded_run(2, 3);
+        assert_eq!(weights(&again), weights(&network));
+        assert_eq!(again_history, history);
+
+        // Another initialization, or another order of the batches, changes the run.
+        let (reinitialized, reinitialized_history) = seeded_run(5, 3);
+        assert_ne!(weights(&reinitialized), weights(&network));
+        assert_ne!(reinitialized_history, history);
+        let (reshuffled, reshuffled_history) = seeded_run(2, 4);
+        assert_ne!(weights(&reshuffled), weights(&network));
+        assert_ne!(reshuffled_history, history);
+    }
+
+    #[test]
+    fn resuming_mid_patience_keeps_the_callback_state() {
+        let directory = std::env::temp_dir().join(format!("trainer-patience-test-{}", std::process::id()));
+        let (inputs, targets) = data();
+        let wrong = flipped(&targets);
+        let validation = Some((&inputs, &wrong));
+        let trainer = |epochs: usize, directory: &Path| {
+            Trainer::new(Box::new(CategoricalCrossEntropy), Adam::new())
+                .with_epochs(epochs)
+                .with_batch_size(8)
+                .with_seed(3)
+                .with_schedule(LrSchedule::reduce_on_plateau(0.01, 0.5, 0, 0.0, 0.0), ScheduleUnit::Epoch)
+                .with_early_stopping(EarlyStopping::new(Metric::ValidationLoss, 4, 0.0))
+                .with_checkpoints(CheckpointPolicy::new(directory, 1, 0))
+        };
+
+        let mut uninterrupted = network();
+        let expected = trainer(50, &directory.join("full")).fit(&mut uninterrupted, &inputs, &targets, validation).unwrap();
+        assert!(expected.epochs.len() < 50);
+
+        // Stop two epochs into the patience, with the learning rate already reduced twice.
+        let best = expected.best_epoch.unwrap();
+        let mut stopped = network();
+        trainer(best + 3, &directory.join("stopped")).fit(&mut stopped, &inputs, &targets, validation).unwrap();
+        let path = checkpoint::latest(directory.join("stopped")).unwrap().unwrap();
+        let mut resumed = network();
+        let mut resuming = trainer(50, &directory.join("stopped"));
+        resuming.resume(&mut resumed, &path).unwrap();
+        let history = resuming.fit(&mut resumed, &inputs, &targets, validation).unwrap();
+
+        assert_eq!(history, expected);
+        assert!(history.epochs.last().unwrap().learning_rate < 0.01 / 4.0);
+        assert_same_weights(&resumed, &uninterrupted);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn an_epoch_without_batches_is_an_error() {
+        let (inputs, targets) = data();
+        let loader = DataLoader::new(InMemoryDataset::new(inputs, targets).unwrap()).with_batch_size(100).with_drop_last(true);
+        let mut trainer = Trainer::new(Box::new(CategoricalCrossEntropy), Adam::new()).with_seed(3);
+        let result = trainer.fit_loader(&mut network(), &loader, None::<&DataLoader<f64, InMemoryDataset<f64>>>);
+        assert!(matches!(result, Err(NnError::EmptyDimension)));
+    }
+
+    #[test]
+    fn a_diverged_run_is_not_checkpointed() {
+        let directory = std::env::temp_dir().join(format!("trainer-diverged-test-{}", std::process::id()));
+        let (inputs, mut targets) = data();
+        targets.values_mut()[0] = f64::NAN;
+        let result = checkpointed(2, &directory).fit(&mut network(), &inputs, &targets, None);
+        assert!(matches!(result, Err(NnError::Diverged { epoch: 0 })));
+        assert!(checkpoint::list(&directory).unwrap().is_empty());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}